@@ -0,0 +1,234 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+// Generational arena: entities live in slots and are referred to by an Id
+// holding the slot index and the slot generation. Removing frees the slot
+// for reuse and bumps its generation, so an old Id never reaches the new
+// entity and removing the same Id twice does nothing.
+//
+// An arena made with_capacity never grows: its slots are allocated once and
+// inserts past the capacity are dropped, so steady state play allocates nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id {
+    index: u32,
+    generation: u32,
+}
+
+// Slot and generation, like 3v1
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>, // Empty slot indexes, reused before growing
+    len: usize,
+    capacity: Option<usize>, // None grows without limit
+    peak: usize,
+    dropped: u64, // Inserts refused because the arena was full
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Arena<T> {
+        Arena::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena { slots: Vec::new(), free: Vec::new(), len: 0, capacity: None, peak: 0, dropped: 0 }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Arena<T> {
+        Arena {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::with_capacity(capacity),
+            len: 0,
+            capacity: Some(capacity),
+            peak: 0,
+            dropped: 0,
+        }
+    }
+
+    // None when a fixed capacity arena is full
+    pub fn insert(&mut self, value: T) -> Option<Id> {
+        if self.capacity.is_some_and(|capacity| self.len >= capacity) {
+            self.dropped += 1;
+            return None;
+        }
+        self.len += 1;
+        self.peak = self.peak.max(self.len);
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            return Some(Id { index, generation: slot.generation });
+        }
+        self.slots.push(Slot { generation: 0, value: Some(value) });
+        Some(Id { index: self.slots.len() as u32 - 1, generation: 0 })
+    }
+
+    // O(1); returns None when the Id was already removed
+    pub fn remove(&mut self, id: Id) -> Option<T> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, id: Id) -> Option<&T> {
+        self.slots.get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut T> {
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    // Most entities alive at once
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // Live entities in slot order, which is stable between ticks
+    pub fn iter(&self) -> impl Iterator<Item = (Id, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            slot.value.as_ref().map(|value| (Id { index: i as u32, generation: slot.generation }, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Id, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let generation = slot.generation;
+            slot.value.as_mut().map(|value| (Id { index: i as u32, generation }, value))
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    // Removes every entity the closure returns false for
+    pub fn retain(&mut self, mut keep: impl FnMut(Id, &mut T) -> bool) {
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            let id = Id { index: i as u32, generation: slot.generation };
+            if let Some(value) = &mut slot.value {
+                if !keep(id, value) {
+                    slot.value = None;
+                    slot.generation = slot.generation.wrapping_add(1);
+                    self.free.push(i as u32);
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl<T> Extend<T> for Arena<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.insert(value);
+        }
+    }
+}
+
+// Indexing is for Ids known to be alive and panics on a removed one. The
+// world only indexes with Ids its broadphase grids handed out this tick,
+// and nothing is removed before the tick ends. Use get for Ids that may be stale.
+impl<T> Index<Id> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: Id) -> &T {
+        self.get(id).expect("entity was removed")
+    }
+}
+
+impl<T> IndexMut<Id> for Arena<T> {
+    fn index_mut(&mut self, id: Id) -> &mut T {
+        self.get_mut(id).expect("entity was removed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removing_twice_returns_none() {
+        let mut arena = Arena::new();
+        let id = arena.insert("bullet").unwrap();
+
+        assert_eq!(arena.remove(id), Some("bullet"));
+        assert_eq!(arena.remove(id), None);
+        assert_eq!(arena.len(), 0);
+    }
+
+    #[test]
+    fn stale_id_misses_the_reused_slot() {
+        let mut arena = Arena::new();
+        let old = arena.insert(1).unwrap();
+        arena.remove(old);
+        let new = arena.insert(2).unwrap();
+
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert_eq!(arena.get(old), None);
+        assert_eq!(arena.remove(old), None);
+        assert_eq!(arena[new], 2);
+    }
+
+    #[test]
+    fn full_arena_drops_inserts() {
+        let mut arena = Arena::with_capacity(2);
+        assert!(arena.insert(1).is_some());
+        assert!(arena.insert(2).is_some());
+
+        assert_eq!(arena.insert(3), None);
+        assert_eq!(arena.insert(4), None);
+        assert_eq!(arena.len(), 2);
+        assert_eq!(arena.dropped(), 2);
+        assert_eq!(arena.peak(), 2);
+    }
+
+    #[test]
+    fn iteration_skips_freed_slots() {
+        let mut arena = Arena::new();
+        let ids: Vec<Id> = (0..5).map(|i| arena.insert(i).unwrap()).collect();
+        arena.remove(ids[1]);
+        arena.retain(|_, value| *value != 3);
+
+        let alive: Vec<(Id, i32)> = arena.iter().map(|(id, value)| (id, *value)).collect();
+        assert_eq!(alive, vec![(ids[0], 0), (ids[2], 2), (ids[4], 4)]);
+        assert_eq!(arena.values().count(), arena.len());
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{Button, KeyCode, MouseButton};
+use ggez::input::{gamepad, keyboard, mouse};
+use serde::{Deserialize, Serialize};
+
+// Where the player's controls are kept, next to the replays
+pub const BINDINGS_FILE: &str = "bindings.toml";
+
+// Most bindings one action can have
+pub const MAX_BINDINGS: usize = 3;
+
+// Everything the player can do, independent of which key does it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Fire,
+    Bomb,
+    Weapon1,
+    Weapon2,
+    Weapon3,
+    Weapon4,
+    Weapon5,
+    NextWeapon,
+    PreviousWeapon,
+    Buy1,
+    Buy2,
+    Buy3,
+    Buy4,
+    Pause,
+    Shop,
+    MenuUp,
+    MenuDown,
+    Confirm,
+    Back,
+    Hitboxes,
+    Stats,
+}
+
+impl Action {
+    // Order of the controls screen
+    pub const ALL: [Action; 25] = [
+        Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight, Action::Fire, Action::Bomb,
+        Action::Weapon1, Action::Weapon2, Action::Weapon3, Action::Weapon4, Action::Weapon5,
+        Action::NextWeapon, Action::PreviousWeapon, Action::Buy1, Action::Buy2, Action::Buy3, Action::Buy4, Action::Pause, Action::Shop,
+        Action::MenuUp, Action::MenuDown, Action::Confirm, Action::Back, Action::Hitboxes, Action::Stats,
+    ];
+
+    // Name in the bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Fire => "fire",
+            Action::Bomb => "bomb",
+            Action::Weapon1 => "weapon_1",
+            Action::Weapon2 => "weapon_2",
+            Action::Weapon3 => "weapon_3",
+            Action::Weapon4 => "weapon_4",
+            Action::Weapon5 => "weapon_5",
+            Action::NextWeapon => "next_weapon",
+            Action::PreviousWeapon => "previous_weapon",
+            Action::Buy1 => "buy_1",
+            Action::Buy2 => "buy_2",
+            Action::Buy3 => "buy_3",
+            Action::Buy4 => "buy_4",
+            Action::Pause => "pause",
+            Action::Shop => "shop",
+            Action::MenuUp => "menu_up",
+            Action::MenuDown => "menu_down",
+            Action::Confirm => "confirm",
+            Action::Back => "back",
+            Action::Hitboxes => "hitboxes",
+            Action::Stats => "stats",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "MOVE UP",
+            Action::MoveDown => "MOVE DOWN",
+            Action::MoveLeft => "MOVE LEFT",
+            Action::MoveRight => "MOVE RIGHT",
+            Action::Fire => "FIRE",
+            Action::Bomb => "BOMB",
+            Action::Weapon1 => "WEAPON 1",
+            Action::Weapon2 => "WEAPON 2",
+            Action::Weapon3 => "WEAPON 3",
+            Action::Weapon4 => "WEAPON 4",
+            Action::Weapon5 => "WEAPON 5",
+            Action::NextWeapon => "NEXT WEAPON",
+            Action::PreviousWeapon => "PREVIOUS WEAPON",
+            Action::Buy1 => "BUY ITEM 1",
+            Action::Buy2 => "BUY ITEM 2",
+            Action::Buy3 => "BUY ITEM 3",
+            Action::Buy4 => "BUY ITEM 4",
+            Action::Pause => "PAUSE",
+            Action::Shop => "SHOP",
+            Action::MenuUp => "MENU UP",
+            Action::MenuDown => "MENU DOWN",
+            Action::Confirm => "CONFIRM",
+            Action::Back => "BACK",
+            Action::Hitboxes => "SHOW HITBOXES",
+            Action::Stats => "SHOW STATS",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(Button), // On any connected gamepad
+}
+
+// Key names used in the bindings file, the same as ggez's KeyCode variants
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(KeyCode, &str)] = &[$((KeyCode::$key, stringify!($key))),*];
+    };
+}
+
+key_names!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Up, Down, Left, Right, Space, Return, Escape, Tab, Back, Delete, Insert, Home, End, PageUp, PageDown,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadEnter,
+    Comma, Period, Slash, Backslash, Semicolon, Apostrophe, Minus, Equals, LBracket, RBracket, Grave,
+);
+
+const MOUSE_NAMES: &[(MouseButton, &str)] = &[
+    (MouseButton::Left, "MouseLeft"),
+    (MouseButton::Right, "MouseRight"),
+    (MouseButton::Middle, "MouseMiddle"),
+];
+
+// Named after the Xbox layout, South is A and RT the right trigger
+const PAD_NAMES: &[(Button, &str)] = &[
+    (Button::South, "PadSouth"),
+    (Button::East, "PadEast"),
+    (Button::North, "PadNorth"),
+    (Button::West, "PadWest"),
+    (Button::LeftTrigger, "PadLB"),
+    (Button::RightTrigger, "PadRB"),
+    (Button::LeftTrigger2, "PadLT"),
+    (Button::RightTrigger2, "PadRT"),
+    (Button::Select, "PadSelect"),
+    (Button::Start, "PadStart"),
+    (Button::LeftThumb, "PadLeftStick"),
+    (Button::RightThumb, "PadRightStick"),
+    (Button::DPadUp, "PadUp"),
+    (Button::DPadDown, "PadDown"),
+    (Button::DPadLeft, "PadLeft"),
+    (Button::DPadRight, "PadRight"),
+];
+
+impl Binding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Binding::Key(key) => KEY_NAMES.iter().find(|(k, _)| k == key).map_or("?", |(_, name)| name),
+            Binding::Mouse(button) => MOUSE_NAMES.iter().find(|(b, _)| b == button).map_or("?", |(_, name)| name),
+            Binding::Pad(button) => PAD_NAMES.iter().find(|(b, _)| b == button).map_or("?", |(_, name)| name),
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Binding> {
+        KEY_NAMES.iter().find(|(_, n)| *n == name).map(|(key, _)| Binding::Key(*key))
+            .or_else(|| MOUSE_NAMES.iter().find(|(_, n)| *n == name).map(|(button, _)| Binding::Mouse(*button)))
+            .or_else(|| PAD_NAMES.iter().find(|(_, n)| *n == name).map(|(button, _)| Binding::Pad(*button)))
+    }
+
+    // Only keys and buttons with a name can be saved
+    pub fn is_supported(&self) -> bool {
+        self.name() != "?"
+    }
+
+    fn is_down(&self, ctx: &Context) -> bool {
+        match self {
+            Binding::Key(key) => keyboard::is_key_pressed(ctx, *key),
+            Binding::Mouse(button) => mouse::button_pressed(ctx, *button),
+            Binding::Pad(button) => gamepad::gamepads(ctx).any(|(_, pad)| pad.is_pressed(*button)),
+        }
+    }
+}
+
+// How far a stick has to be pushed, as a fraction of full tilt, before it
+// counts. Worn sticks rest slightly off center and would drift without it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Deadzones {
+    pub movement: f32, // Left stick
+    pub aim: f32, // Right stick
+}
+
+impl Default for Deadzones {
+    fn default() -> Deadzones {
+        Deadzones { movement: 0.2, aim: 0.3 }
+    }
+}
+
+// Values the settings screen steps through
+const DEADZONE_STEPS: [f32; 7] = [0.05, 0.1, 0.15, 0.2, 0.25, 0.3, 0.4];
+
+impl Deadzones {
+    // The step after `value`, wrapping around to the smallest
+    pub fn next_step(value: f32) -> f32 {
+        DEADZONE_STEPS.iter().copied().find(|&step| step > value + 0.001).unwrap_or(DEADZONE_STEPS[0])
+    }
+}
+
+// The bindings file: every action's name with the names of its keys
+#[derive(Serialize, Deserialize, Default)]
+struct BindingsFile {
+    bindings: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    deadzones: Deadzones,
+}
+
+// Which keys, mouse and gamepad buttons trigger each action, several per action
+#[derive(Clone)]
+pub struct Bindings {
+    map: BTreeMap<Action, Vec<Binding>>,
+    pub deadzones: Deadzones,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        use Binding::{Key, Mouse, Pad};
+        let defaults = [
+            (Action::MoveUp, vec![Key(KeyCode::W), Pad(Button::DPadUp)]),
+            (Action::MoveDown, vec![Key(KeyCode::S), Pad(Button::DPadDown)]),
+            (Action::MoveLeft, vec![Key(KeyCode::A), Pad(Button::DPadLeft)]),
+            (Action::MoveRight, vec![Key(KeyCode::D), Pad(Button::DPadRight)]),
+            (Action::Fire, vec![Key(KeyCode::Space), Mouse(MouseButton::Left), Pad(Button::RightTrigger2)]),
+            (Action::Bomb, vec![Key(KeyCode::E), Mouse(MouseButton::Right), Pad(Button::LeftTrigger2)]),
+            (Action::Weapon1, vec![Key(KeyCode::Key1)]),
+            (Action::Weapon2, vec![Key(KeyCode::Key2)]),
+            (Action::Weapon3, vec![Key(KeyCode::Key3)]),
+            (Action::Weapon4, vec![Key(KeyCode::Key4)]),
+            (Action::Weapon5, vec![Key(KeyCode::Key5)]),
+            (Action::NextWeapon, vec![Key(KeyCode::Q), Pad(Button::RightTrigger)]),
+            (Action::PreviousWeapon, vec![Pad(Button::LeftTrigger)]),
+            (Action::Buy1, vec![Key(KeyCode::Key1), Pad(Button::West)]),
+            (Action::Buy2, vec![Key(KeyCode::Key2), Pad(Button::North)]),
+            (Action::Buy3, vec![Key(KeyCode::Key3), Pad(Button::LeftTrigger)]),
+            (Action::Buy4, vec![Key(KeyCode::Key4), Pad(Button::RightTrigger)]),
+            (Action::Pause, vec![Key(KeyCode::Escape), Key(KeyCode::P), Pad(Button::Start)]),
+            (Action::Shop, vec![Key(KeyCode::Tab), Pad(Button::Select)]),
+            (Action::MenuUp, vec![Key(KeyCode::Up), Key(KeyCode::W), Pad(Button::DPadUp)]),
+            (Action::MenuDown, vec![Key(KeyCode::Down), Key(KeyCode::S), Pad(Button::DPadDown)]),
+            (Action::Confirm, vec![Key(KeyCode::Return), Key(KeyCode::Space), Pad(Button::South)]),
+            (Action::Back, vec![Key(KeyCode::Escape), Pad(Button::East)]),
+            (Action::Hitboxes, vec![Key(KeyCode::F1)]),
+            (Action::Stats, vec![Key(KeyCode::F2)]),
+        ];
+        Bindings { map: defaults.into_iter().collect(), deadzones: Deadzones::default() }
+    }
+}
+
+impl Bindings {
+    // Reads BINDINGS_FILE, actions missing from it keep their default keys
+    pub fn load() -> GameResult<Bindings> {
+        let mut bindings = Bindings::default();
+        let path = Path::new(BINDINGS_FILE);
+        if !path.exists() {
+            return Ok(bindings);
+        }
+
+        let text = fs::read_to_string(path)?;
+        let file: BindingsFile = toml::from_str(&text)
+            .map_err(|e| GameError::ConfigError(format!("{}: {}", BINDINGS_FILE, e)))?;
+        for (action_name, names) in file.bindings {
+            let action = Action::ALL.iter().copied().find(|action| action.name() == action_name)
+                .ok_or_else(|| GameError::ConfigError(format!("{}: unknown action {}", BINDINGS_FILE, action_name)))?;
+            let parsed = names.iter()
+                .map(|name| Binding::parse(name)
+                    .ok_or_else(|| GameError::ConfigError(format!("{}: unknown key {}", BINDINGS_FILE, name))))
+                .collect::<GameResult<Vec<Binding>>>()?;
+            bindings.map.insert(action, parsed.into_iter().take(MAX_BINDINGS).collect());
+        }
+        bindings.deadzones = file.deadzones;
+        Ok(bindings)
+    }
+
+    pub fn save(&self) -> GameResult {
+        let file = BindingsFile {
+            bindings: self.map.iter()
+                .map(|(action, bindings)| (action.name().to_string(), bindings.iter().map(|b| b.name().to_string()).collect()))
+                .collect(),
+            deadzones: self.deadzones,
+        };
+        let text = toml::to_string(&file).map_err(|e| GameError::ConfigError(e.to_string()))?;
+        fs::write(BINDINGS_FILE, text)?;
+        Ok(())
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.map.get(&action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    // Held right now, for continuous actions like moving and firing
+    pub fn is_down(&self, ctx: &Context, action: Action) -> bool {
+        self.get(action).iter().any(|binding| binding.is_down(ctx))
+    }
+
+    // Whether a pressed key or button is bound to the action, for one-shot actions
+    pub fn triggers(&self, action: Action, pressed: Binding) -> bool {
+        self.get(action).contains(&pressed)
+    }
+
+    // Adds a binding, replacing the oldest one when the action already has MAX_BINDINGS
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.map.entry(action).or_default();
+        if bindings.contains(&binding) {
+            return;
+        }
+        if bindings.len() >= MAX_BINDINGS {
+            bindings.remove(0);
+        }
+        bindings.push(binding);
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.map.insert(action, Vec::new());
+    }
+
+    // Names of all the action's bindings, for the controls screen
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<&str> = self.get(action).iter().map(|binding| binding.name()).collect();
+        if names.is_empty() { "-".to_string() } else { names.join(" / ") }
+    }
+}
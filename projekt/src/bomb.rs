@@ -0,0 +1,98 @@
+use ggez::GameResult;
+use ggez::graphics::{Color, Rect};
+use nalgebra as na;
+
+use crate::arena::Id;
+use crate::collider::Collider;
+use crate::render::Renderer;
+
+// Most bomb charges the player can carry
+pub const MAX_BOMBS: u32 = 5;
+// Charges at the start of a run
+pub const STARTING_BOMBS: u32 = 2;
+
+// The shockwave grows this fast until it reaches its full size
+const SHOCKWAVE_SPEED: f32 = 1600.0;
+const SHOCKWAVE_RADIUS: f32 = 800.0;
+// Damage to every enemy the shockwave reaches, bosses only take a share of it
+const SHOCKWAVE_DAMAGE: i32 = 20;
+const BOSS_DAMAGE_SHARE: f32 = 0.25;
+
+// Dropped bombs disappear after this many seconds
+const PICKUP_LIFETIME: f32 = 10.0;
+const PICKUP_RADIUS: f32 = 14.0;
+
+// Expanding ring from a bomb. Enemy bullets inside it are cancelled and every
+// enemy it reaches is hurt once.
+pub struct Shockwave {
+    pub center: na::Point2<f32>,
+    pub radius: f32,
+    pub prev_radius: f32,
+    hit: Vec<Id>, // Enemies already hurt by this shockwave
+}
+
+impl Shockwave {
+    pub fn new(center: na::Point2<f32>) -> Shockwave {
+        Shockwave { center, radius: 0.0, prev_radius: 0.0, hit: Vec::new() }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.prev_radius = self.radius;
+        self.radius = (self.radius + SHOCKWAVE_SPEED * dt).min(SHOCKWAVE_RADIUS);
+    }
+
+    // Done after the tick it reached its full size
+    pub fn is_done(&self) -> bool {
+        self.prev_radius >= SHOCKWAVE_RADIUS
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.center.x - self.radius, self.center.y - self.radius, self.radius * 2.0, self.radius * 2.0)
+    }
+
+    pub fn contains(&self, pos: &na::Point2<f32>) -> bool {
+        na::distance(&self.center, pos) <= self.radius
+    }
+
+    // Damage for an enemy the first time the shockwave reaches it, None after that
+    pub fn hit(&mut self, enemy: Id, is_boss: bool) -> Option<i32> {
+        if self.hit.contains(&enemy) {
+            return None;
+        }
+        self.hit.push(enemy);
+        let damage = if is_boss { (SHOCKWAVE_DAMAGE as f32 * BOSS_DAMAGE_SHARE).round() as i32 } else { SHOCKWAVE_DAMAGE };
+        Some(damage)
+    }
+
+    // A ring that fades out as it grows
+    pub fn draw(&self, renderer: &mut Renderer, alpha: f32) -> GameResult {
+        let radius = self.prev_radius + (self.radius - self.prev_radius) * alpha;
+        let fade = 1.0 - radius / SHOCKWAVE_RADIUS;
+        renderer.ring(self.center, radius.max(1.0), 12.0, Color::new(1.0, 0.9, 0.5, 0.2 + 0.6 * fade))
+    }
+}
+
+// A bomb charge dropped by a killed enemy, the player picks it up by touching it
+pub struct BombPickup {
+    pub pos: na::Point2<f32>,
+    pub time_left: f32,
+}
+
+impl BombPickup {
+    pub fn new(pos: na::Point2<f32>) -> BombPickup {
+        BombPickup { pos, time_left: PICKUP_LIFETIME }
+    }
+
+    pub fn collider(&self) -> Collider {
+        Collider::circle(PICKUP_RADIUS)
+    }
+
+    // Blinks during its last 3 seconds
+    pub fn draw(&self, renderer: &mut Renderer) -> GameResult {
+        if self.time_left < 3.0 && (self.time_left * 8.0) as i32 % 2 == 0 {
+            return Ok(());
+        }
+        renderer.circle(self.pos, PICKUP_RADIUS, Color::from_rgb(40, 40, 40))?;
+        renderer.ring(self.pos, PICKUP_RADIUS, 3.0, Color::from_rgb(255, 200, 0))
+    }
+}
@@ -1,221 +1,305 @@
-use ggez::{Context, GameResult};
-use ggez::graphics::{self, Image, DrawParam};
+use std::collections::BTreeMap;
+
+use ggez::{GameError, GameResult};
+use ggez::graphics::{Color, DrawParam, Rect};
 use nalgebra as na;
-use std::f32::consts::PI;
+use rand::Rng;
+use serde::Deserialize;
 use crate::player::Player;
-use crate::bullet::Bullet;
+use crate::bullet::BulletPool;
+use crate::pattern::{Emitter, PatternLibrary, ShotStats};
 use crate::enemy::Enemy;
-use ggez::timer;
-use std::fmt;
+use crate::render::Renderer;
+use crate::catalogue::{EnemyCatalogue, EnemyDef};
+use crate::collider::Collider;
+use crate::resources::load_toml;
+use crate::world::{interpolate, GameRng};
+
+// Seconds the boss holds still between attacks when it enters a new phase
+const PHASE_CHANGE_PAUSE: f32 = 1.0;
+// The telegraph ring starts this many times the boss's size away and closes in
+const TELEGRAPH_RING_SCALE: f32 = 1.5;
+
+// One attack as written in resources/boss.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct AttackDef {
+    pub id: String,
+    pub pattern: String,
+    pub telegraph: f32,
+    pub duration: f32,
+    pub cooldown: f32,
+    #[serde(default = "default_color")]
+    pub color: [u8; 3], // Of the telegraph
+    #[serde(default)]
+    next: BTreeMap<String, u32>,
+    // `pattern` as a pattern index, filled in when loading
+    #[serde(skip)]
+    pub pattern_index: usize,
+    // `next` as attack indices, filled in when loading
+    #[serde(skip)]
+    pub next_weights: Vec<(usize, u32)>,
+}
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct PhaseDef {
+    pub below: f32, // Share of max HP
+    #[serde(default = "default_multiplier")]
+    pub speed: f32,
+    attacks: BTreeMap<String, u32>,
+    // `attacks` as attack indices, filled in when loading
+    #[serde(skip)]
+    pub weights: Vec<(usize, u32)>,
+}
 
-pub enum BossState {
-    Idle,
-    AttackNormal,
-    AttackCircle,
-    AttackMachine,
+fn default_multiplier() -> f32 {
+    1.0
 }
 
-impl fmt::Debug for BossState {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let state_str = match self {
-            BossState::Idle => "Idle",
-            BossState::AttackNormal => "AttackNormal",
-            BossState::AttackCircle => "AttackCircle",
-            BossState::AttackMachine => "AttackMachine",
+fn default_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+// Contents of resources/boss.toml
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct BossScript {
+    pub attacks: Vec<AttackDef>,
+    pub phases: Vec<PhaseDef>,
+}
+
+impl BossScript {
+    pub fn load(patterns: &PatternLibrary) -> GameResult<BossScript> {
+        let script: BossScript = load_toml("boss.toml")?;
+        script.resolve(patterns)
+    }
+
+    // Checks the script and turns its ids into indices
+    fn resolve(self, patterns: &PatternLibrary) -> GameResult<BossScript> {
+        let mut script = self;
+        let error = |message: String| GameError::ResourceLoadError(format!("resources/boss.toml: {}", message));
+
+        for (i, attack) in script.attacks.iter().enumerate() {
+            if script.attacks[..i].iter().any(|other| other.id == attack.id) {
+                return Err(error(format!("{} is listed twice", attack.id)));
+            }
+            if attack.telegraph < 0.0 || attack.duration < 0.0 || attack.cooldown < 0.0 {
+                return Err(error(format!("{} can't have negative times", attack.id)));
+            }
+        }
+        for attack in &mut script.attacks {
+            attack.pattern_index = patterns.find(&attack.pattern)
+                .ok_or_else(|| error(format!("{} fires unknown pattern {}", attack.id, attack.pattern)))?;
+        }
+
+        // Highest threshold first, so the active phase is the last one the HP is under
+        script.phases.sort_by(|a, b| b.below.total_cmp(&a.below));
+        if script.phases.first().is_none_or(|phase| phase.below < 1.0) {
+            return Err(error("the first phase needs below = 1.0".to_string()));
+        }
+
+        let attacks = script.attacks.clone();
+        let resolve = |weights: &BTreeMap<String, u32>| -> GameResult<Vec<(usize, u32)>> {
+            weights.iter()
+                .map(|(id, &weight)| match attacks.iter().position(|attack| &attack.id == id) {
+                    Some(i) => Ok((i, weight)),
+                    None => Err(error(format!("unknown attack {}", id))),
+                })
+                .collect()
         };
-        write!(f, "{}", state_str)
+        for attack in &mut script.attacks {
+            attack.next_weights = resolve(&attack.next)?;
+        }
+        for phase in &mut script.phases {
+            phase.weights = resolve(&phase.attacks)?;
+            if phase.weights.iter().map(|&(_, weight)| weight).sum::<u32>() == 0 {
+                return Err(error(format!("the phase below {} has no attacks", phase.below)));
+            }
+        }
+        Ok(script)
     }
+
+    // Index of the phase for the boss's current HP
+    fn phase(&self, hp: i32, max_hp: i32) -> usize {
+        let share = hp as f32 / max_hp as f32;
+        self.phases.iter().rposition(|phase| share <= phase.below).unwrap_or(0)
+    }
+
+    // Weighted roll for the attack after `last`. Its `next` weights win, but
+    // only for attacks the phase allows.
+    fn pick(&self, phase: usize, last: Option<usize>, rng: &mut GameRng) -> usize {
+        let allowed = &self.phases[phase].weights;
+        let follow_ups = last.map_or(&[][..], |last| &self.attacks[last].next_weights[..]);
+        let follow_ups = follow_ups.iter()
+            .filter(|(attack, _)| allowed.iter().any(|(other, weight)| other == attack && *weight > 0))
+            .copied();
+        if follow_ups.clone().any(|(_, weight)| weight > 0) {
+            weighted_roll(follow_ups, rng)
+        } else {
+            weighted_roll(allowed.iter().copied(), rng)
+        }
+    }
+}
+
+// Attack picked from (attack, weight) pairs with a chance of its share of the weights
+fn weighted_roll(weights: impl Iterator<Item = (usize, u32)> + Clone, rng: &mut GameRng) -> usize {
+    let mut roll = rng.gen_range(0..weights.clone().map(|(_, weight)| weight).sum::<u32>());
+    let mut picked = 0;
+    for (attack, weight) in weights {
+        picked = attack;
+        if roll < weight {
+            break;
+        }
+        roll -= weight;
+    }
+    picked
+}
+
+// Where the boss is in its attack cycle, times are seconds left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BossState {
+    Idle { left: f32 },
+    Telegraph { attack: usize, left: f32 },
+    Attacking { attack: usize, left: f32 },
 }
 
 pub struct Boss {
     pub size: f32,
     pub pos: na::Point2<f32>,
+    pub prev_pos: na::Point2<f32>,
     pub hp: i32,
     pub max_hp: i32,
     pub speed: f32,
     pub damage: i32,
-    pub bullet_speed: f32,
-    pub shoot_cooldown: f32,
-    pub attack_timer: f32,
+    pub shot: ShotStats,
+    pub emitter: Emitter,
     pub current_state: BossState,
+    pub phase: usize,
+    pub last_attack: Option<usize>,
     pub coins: i32,
     pub points: i32,
 
-    pub circle_bullets_count: usize,
-
-    pub sprite: Image,
+    pub collider: Collider,
+    pub archetype: usize,
+    pub sprite_scale: f32,
 }
 
 
 impl Boss {
-    pub fn new(pos: na::Point2<f32>, level: i32, image: Image) -> Self {
+    pub fn new(pos: na::Point2<f32>, level: i32, def: &EnemyDef, archetype: usize) -> Self {
         Boss {
-            size: 100.0,
+            size: def.size[0],
             pos,
-            hp: 100 * level,
-            max_hp: 100 * level,
-            speed: 1.0,
-            damage: 8 * level,
-            bullet_speed: 4.0 * level as f32,
-            shoot_cooldown: 3.0,
-            attack_timer: 0.0,
-            current_state: BossState::Idle,
-            coins: 1000 * level,
-            points: 100 * level,
-
-            circle_bullets_count: 8 * level as usize,
-            sprite: image
+            prev_pos: pos,
+            hp: def.hp.at_i32(level),
+            max_hp: def.hp.at_i32(level),
+            speed: def.speed.at(level),
+            damage: def.damage.at_i32(level),
+            shot: ShotStats { damage: def.damage.at_i32(level), speed: def.bullet_speed.at(level), size: def.bullet_size, level },
+            emitter: Emitter::new(),
+            current_state: BossState::Idle { left: PHASE_CHANGE_PAUSE },
+            phase: 0,
+            last_attack: None,
+            coins: def.coins.at_i32(level),
+            points: def.points.at_i32(level),
+
+            collider: def.collider(),
+            archetype,
+            sprite_scale: def.sprite_scale,
         }
     }
 
-    fn choose_random_move(&mut self) {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        let random_move = rng.gen_range(0..3);
-
-        self.current_state = match random_move {
-            0 => BossState::AttackNormal,
-            1 => BossState::AttackCircle,
-            2 => BossState::AttackMachine,
-            _ => unreachable!(),
-        };
+    // Bullets leave from the middle of the sprite
+    fn center(&self) -> na::Point2<f32> {
+        self.collider.center(&self.pos)
     }
 
-    fn move_towards_player(&mut self, target: &Player) {
-        let dir = (target.player_pos - &self.pos).normalize();
-        self.pos.x += dir.x * self.speed;
-        self.pos.y += dir.y * self.speed;
+    fn move_towards_player(&mut self, target: &Player, script: &BossScript, dt: f32) {
+        let dir = (target.player_pos - self.pos).normalize();
+        let speed = self.speed * script.phases[self.phase].speed;
+        self.pos.x += dir.x * speed * dt;
+        self.pos.y += dir.y * speed * dt;
     }
 
-    fn shoot_pattern(&mut self, ctx: &mut Context) -> GameResult<Vec<Bullet>> {
-        // Shooting on bullet each direction pattern
-        let mut bullets = Vec::new();
-
-        // Only shoot when cooldown has passed
-        self.attack_timer += timer::delta(ctx).as_secs_f32();
-        if self.attack_timer >= self.shoot_cooldown {
-            let directions = vec![
-                na::Point2::new(self.pos.x, self.pos.y + 1.0), // Down
-                na::Point2::new(self.pos.x, self.pos.y - 1.0), // Up
-                na::Point2::new(self.pos.x - 1.0, self.pos.y), // Left
-                na::Point2::new(self.pos.x + 1.0, self.pos.y), // Right
-            ];
-
-            // Shoot in each direction
-            for target in directions {
-                let bullet = Bullet::new(self.pos, target, self.bullet_speed, self.damage, 20.0);
-                bullets.push(bullet);
-            }
-
-            // Reset the attack timer after shooting
-            self.attack_timer = 0.0;
+    // Counts the current state down and moves on to the next one when it runs out
+    fn update_state(&mut self, player: &Player, dt: f32, bullets: &mut BulletPool, catalogue: &EnemyCatalogue, rng: &mut GameRng) {
+        let script = &catalogue.boss_script;
+        // A new phase cuts the running attack short
+        let phase = script.phase(self.hp, self.max_hp);
+        if phase != self.phase {
+            self.phase = phase;
+            self.current_state = BossState::Idle { left: PHASE_CHANGE_PAUSE };
+            self.emitter.stop();
         }
 
-        Ok(bullets)
-    }
-
-    fn shoot_pattern_circle(&mut self, ctx: &mut Context, bullets_in_burst: usize) -> GameResult<Vec<Bullet>> {
-        // Shooting in a circle
-        let mut bullets = Vec::new();
-        let angle_step = 2.0 * PI / (bullets_in_burst as f32); // Calculate the angles between each bullet
-
-        // Only shoot when cooldown has passed
-        self.attack_timer += timer::delta(ctx).as_secs_f32();
-        if self.attack_timer >= self.shoot_cooldown {
-            for i in 0..bullets_in_burst {
-                let angle = i as f32 * angle_step;
-                let dir_x = angle.cos();
-                let dir_y = angle.sin();
-                let target = na::Point2::new(self.pos.x + dir_x, self.pos.y + dir_y);
-                let bullet = Bullet::new(self.pos, target, self.bullet_speed, self.damage, 20.0);
-                bullets.push(bullet);
+        self.current_state = match self.current_state {
+            BossState::Idle { left } if left > dt => BossState::Idle { left: left - dt },
+            BossState::Idle { .. } => {
+                let attack = script.pick(self.phase, self.last_attack, rng);
+                BossState::Telegraph { attack, left: script.attacks[attack].telegraph }
             }
-
-            self.attack_timer = 0.0; // Reset the attack timer after shooting
-        }
-
-        Ok(bullets)
+            BossState::Telegraph { attack, left } if left > dt => BossState::Telegraph { attack, left: left - dt },
+            BossState::Telegraph { attack, .. } => {
+                self.emitter.start(script.attacks[attack].pattern_index, self.shot);
+                BossState::Attacking { attack, left: script.attacks[attack].duration }
+            }
+            BossState::Attacking { attack, left } if left > dt => BossState::Attacking { attack, left: left - dt },
+            BossState::Attacking { attack, .. } => {
+                // Whatever the pattern still had to fire is cut off
+                self.emitter.stop();
+                self.last_attack = Some(attack);
+                BossState::Idle { left: script.attacks[attack].cooldown }
+            }
+        };
+        // Add to main enemy bullet pool
+        self.emitter.update(&catalogue.patterns, dt, self.center(), player.player_pos, bullets, rng);
     }
-    
 
-    fn shoot_big_bullet(&mut self, ctx: &mut Context, target: na::Point2<f32>) -> GameResult<Vec<Bullet>> {
-        // Shooting big bullet 
-        let mut bullets = Vec::new();
-    
-        self.attack_timer += timer::delta(ctx).as_secs_f32();
-        if self.attack_timer >= self.shoot_cooldown  {
-            let bullet = Bullet::new(self.pos, target, self.bullet_speed * 2.0, self.damage * 2, 50.0);
-            bullets.push(bullet);
-            self.attack_timer = 0.0;  // Reset timer after firing
-        }
-
-        Ok(bullets)
+    // Ring closing in on the boss and a flashing sprite while an attack winds up
+    fn draw_telegraph(&self, script: &BossScript, renderer: &mut Renderer, pos: na::Point2<f32>) -> GameResult<Color> {
+        let (attack, left) = match self.current_state {
+            BossState::Telegraph { attack, left } => (attack, left),
+            _ => return Ok(Color::WHITE),
+        };
+        let def = &script.attacks[attack];
+        let color = Color::from_rgb(def.color[0], def.color[1], def.color[2]);
+        let progress = if def.telegraph > 0.0 { 1.0 - left / def.telegraph } else { 1.0 };
+        let radius = self.size * (TELEGRAPH_RING_SCALE - (TELEGRAPH_RING_SCALE - 0.5) * progress);
+        renderer.ring(self.collider.center(&pos), radius, 4.0, color)?;
+
+        // Flashes faster the closer the attack is
+        let flash = ((progress * progress * 12.0) as i32) % 2 == 0;
+        Ok(if flash { color } else { Color::WHITE })
     }
 
-    fn draw_hp(&self, ctx: &mut Context) -> GameResult {
+    fn draw_hp(&self, renderer: &mut Renderer, pos: na::Point2<f32>) -> GameResult {
         // Drawing hp container
-        let background_rect = graphics::Rect::new(self.pos.x, self.pos.y - 35.0, self.size, 5.0);
-        let background_mesh = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), background_rect, graphics::Color::from_rgb(0, 0, 0))?;
-        graphics::draw(ctx, &background_mesh, graphics::DrawParam::default())?;
+        let background_rect = Rect::new(pos.x, pos.y - 35.0, self.size, 5.0);
+        renderer.rect(background_rect, Color::from_rgb(0, 0, 0))?;
 
         // Drawing hp
         let hp_width = (self.hp as f32 / self.max_hp as f32) * self.size;
-        let hp_rect = graphics::Rect::new(self.pos.x, self.pos.y - 35.0, hp_width, 5.0);
-        let hp_mesh = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), hp_rect, graphics::Color::from_rgb(0, 255, 0))?;
-        graphics::draw(ctx, &hp_mesh, graphics::DrawParam::default())?;
-
-        Ok(())
+        let hp_rect = Rect::new(pos.x, pos.y - 35.0, hp_width, 5.0);
+        renderer.rect(hp_rect, Color::from_rgb(0, 255, 0))
     }
 }
 
 impl Enemy for Boss {
-    fn update(&mut self, player: &Player, ctx: &mut Context, game_bullets: &mut Vec<Bullet>) {
-        self.move_towards_player(&player);
-
-        // Update boss state based on current state
-        // Choosing with random witch attack to use
-        match self.current_state {
-            BossState::AttackNormal => {
-                if let Ok(bullets) = self.shoot_pattern(ctx) {
-                    game_bullets.extend(bullets); // Add to main enemy bullet array
-                }
-                self.choose_random_move();
-            }
-            BossState::AttackCircle => {
-                if let Ok(bullets) = self.shoot_pattern_circle(ctx, self.circle_bullets_count) {
-                    game_bullets.extend(bullets); // Add to main enemy bullet array
-                } 
-                self.choose_random_move();
-            }
-            BossState::AttackMachine => {
-                if let Ok(bullets) = self.shoot_big_bullet(ctx, player.player_pos) {
-                    game_bullets.extend(bullets); // Add to main enemy bullet array
-                }
-                self.choose_random_move();
-            }
-            BossState::Idle => {
-                self.choose_random_move();
-            }
-        }
-        //println!("Boss state: {:?}", self.current_state);
+    fn update(&mut self, player: &Player, dt: f32, game_bullets: &mut BulletPool, catalogue: &EnemyCatalogue, rng: &mut GameRng) {
+        self.prev_pos = self.pos;
+        self.move_towards_player(player, &catalogue.boss_script, dt);
+        self.update_state(player, dt, game_bullets, catalogue, rng);
     }
 
-    fn draw(&self, ctx: &mut Context) -> GameResult {
-        graphics::draw(ctx, &self.sprite, DrawParam::default().dest([self.pos.x, self.pos.y]).scale([2.0, 2.0]))?;
-        self.draw_hp(ctx)?;
+    fn draw(&self, catalogue: &EnemyCatalogue, renderer: &mut Renderer, alpha: f32) -> GameResult {
+        let pos = interpolate(&self.prev_pos, &self.pos, alpha);
+        let tint = self.draw_telegraph(&catalogue.boss_script, renderer, pos)?;
+        renderer.enemy(self.archetype, DrawParam::default().dest([pos.x, pos.y]).scale([self.sprite_scale, self.sprite_scale]).color(tint));
+        self.draw_hp(renderer, pos)?;
 
         Ok(())
     }
     
-    fn check_collision(&self, player: &Player) -> bool {
-        let enemy_rect = graphics::Rect::new(self.pos.x - 25.0, self.pos.y - 25.0, self.size, self.size);
-        let player_rect = graphics::Rect::new(player.player_pos.x - 10.0, player.player_pos.y - 10.0, 20.0, 20.0);
-        enemy_rect.overlaps(&player_rect)
-    }
-
-    fn apply_damage(&self, player: &mut Player) {
-        player.take_damage(self.damage);
+    fn contact_damage(&self) -> i32 {
+        self.damage
     }
 
     fn take_damage(&mut self, damage: i32) -> i32 {
@@ -228,6 +312,10 @@ impl Enemy for Boss {
 
     fn is_boss(&self) -> bool { true }
 
+    fn archetype(&self) -> usize {
+        self.archetype
+    }
+
     fn get_pos(&self) -> &na::Point2<f32> {
         &self.pos
     }
@@ -244,8 +332,164 @@ impl Enemy for Boss {
         self.points
     }
 
-    fn get_size(&self) -> f32 {
-        self.size
+    fn collider(&self) -> &Collider {
+        &self.collider
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bullet::BULLET_CAPACITY;
+    use crate::weapon::WeaponLibrary;
+    use crate::world::FIXED_DT;
+
+    const SLAM: usize = 0;
+    const SWEEP: usize = 1;
+    const RAGE: usize = 2;
+
+    // A standing boss whose slam is always followed by a sweep, and who only
+    // rages below half HP
+    fn test_catalogue() -> EnemyCatalogue {
+        let mut catalogue: EnemyCatalogue = toml::from_str(r#"
+            [[enemies]]
+            name = "boss"
+            behaviour = "boss"
+            sprite = "/boss.png"
+            sprite_scale = 1.0
+            collider = "box"
+            hp = { base = 100 }
+            speed = { base = 0 }
+            damage = { base = 1 }
+            coins = { base = 0 }
+            points = { base = 0 }
+            bullet_speed = { base = 100 }
+            bullet_size = 10.0
+        "#).unwrap();
+        catalogue.enemies[0].size = [100.0, 100.0];
+        catalogue.patterns = toml::from_str(r#"
+            [[patterns]]
+            id = "shot"
+            shape = "fan"
+        "#).unwrap();
+        let script: BossScript = toml::from_str(r#"
+            [[attacks]]
+            id = "slam"
+            pattern = "shot"
+            telegraph = 0.5
+            duration = 1.0
+            cooldown = 0.25
+            next = { sweep = 1 }
+
+            [[attacks]]
+            id = "sweep"
+            pattern = "shot"
+            telegraph = 0.5
+            duration = 1.0
+            cooldown = 0.25
+
+            [[attacks]]
+            id = "rage"
+            pattern = "shot"
+            telegraph = 0.5
+            duration = 1.0
+            cooldown = 0.25
+
+            [[phases]]
+            below = 1.0
+            attacks = { slam = 3, sweep = 1 }
+
+            [[phases]]
+            below = 0.5
+            attacks = { rage = 1 }
+        "#).unwrap();
+        catalogue.boss_script = script.resolve(&catalogue.patterns).unwrap();
+        catalogue
+    }
+
+    struct Fight {
+        boss: Boss,
+        player: Player,
+        bullets: BulletPool,
+        catalogue: EnemyCatalogue,
+        rng: GameRng,
+    }
+
+    impl Fight {
+        fn new() -> Fight {
+            let catalogue = test_catalogue();
+            let weapons: WeaponLibrary = toml::from_str(r#"
+                [[weapons]]
+                id = "pistol"
+                name = "PISTOL"
+                kind = "gun"
+            "#).unwrap();
+            Fight {
+                boss: Boss::new(na::Point2::new(100.0, 100.0), 1, &catalogue.enemies[0], 0),
+                player: Player::new([40.0, 40.0], &weapons).unwrap(),
+                bullets: BulletPool::with_capacity(BULLET_CAPACITY),
+                catalogue,
+                rng: GameRng::seed_from_u64(1),
+            }
+        }
+
+        fn run(&mut self, seconds: f32) {
+            for _ in 0..(seconds / FIXED_DT).round() as usize {
+                self.boss.update(&self.player, FIXED_DT, &mut self.bullets, &self.catalogue, &mut self.rng);
+            }
+        }
+    }
+
+    #[test]
+    fn attacks_go_through_telegraph_attack_and_cooldown() {
+        let mut fight = Fight::new();
+
+        fight.run(0.75);
+        assert!(matches!(fight.boss.current_state, BossState::Idle { .. }));
+        fight.run(0.5);
+        assert!(matches!(fight.boss.current_state, BossState::Telegraph { .. }));
+        assert_eq!(fight.bullets.len(), 0);
+        fight.run(0.5);
+        let attack = match fight.boss.current_state {
+            BossState::Attacking { attack, .. } => attack,
+            state => panic!("expected an attack, got {:?}", state),
+        };
+        assert_eq!(fight.bullets.len(), 1);
+        fight.run(0.9);
+        assert!(matches!(fight.boss.current_state, BossState::Idle { .. }));
+        assert_eq!(fight.boss.last_attack, Some(attack));
+        fight.run(0.25);
+        assert!(matches!(fight.boss.current_state, BossState::Telegraph { .. }));
+    }
+
+    #[test]
+    fn losing_hp_starts_the_next_phase() {
+        let mut fight = Fight::new();
+        fight.run(1.75);
+        assert!(matches!(fight.boss.current_state, BossState::Attacking { .. }));
+
+        fight.boss.take_damage(50);
+        fight.run(FIXED_DT);
+        assert_eq!(fight.boss.phase, 1);
+        assert!(matches!(fight.boss.current_state, BossState::Idle { left } if left > PHASE_CHANGE_PAUSE - 2.0 * FIXED_DT));
+        assert!(!fight.boss.emitter.is_firing());
+
+        fight.run(PHASE_CHANGE_PAUSE + 0.25);
+        assert!(matches!(fight.boss.current_state, BossState::Telegraph { attack: RAGE, .. }));
+    }
+
+    #[test]
+    fn follow_ups_win_while_the_phase_allows_them() {
+        let script = &test_catalogue().boss_script;
+        let mut rng = GameRng::seed_from_u64(7);
+
+        assert!((0..100).all(|_| script.pick(0, Some(SLAM), &mut rng) == SWEEP));
+        // The sweep has no follow-ups and the rage phase doesn't allow the slam's
+        assert!((0..100).all(|_| script.pick(1, Some(SLAM), &mut rng) == RAGE));
+        let slams = (0..4000).filter(|_| script.pick(0, Some(SWEEP), &mut rng) == SLAM).count();
+        assert!((2850..3150).contains(&slams), "{} slams out of 4000 with weights 3 to 1", slams);
+    }
+}
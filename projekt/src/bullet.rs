@@ -1,57 +1,210 @@
-use ggez::{Context, GameResult};
-use ggez::graphics::{self, Color, DrawParam};
+use ggez::GameResult;
+use ggez::graphics::{self, Color};
 use nalgebra as na;
+use serde::Deserialize;
+use std::f32::consts::{PI, TAU};
 use crate::player::Player;
 use crate::enemy::Enemy;
+use crate::world::interpolate;
+use crate::collider::Collider;
+use crate::render::Renderer;
+use crate::playfield;
+use crate::arena::{Arena, Id};
+use crate::pattern::ShotStats;
+
+// Bullets of both teams live in one fixed size pool, shots past the limit are skipped
+pub type BulletPool = Arena<Bullet>;
+pub const BULLET_CAPACITY: usize = 10240;
+
+// Enemies a bullet remembers hitting, so a piercing bullet hurts each one once
+const HIT_MEMORY: usize = 8;
+// A bullet can't pierce more enemies than it can remember
+pub const MAX_PIERCE: u32 = HIT_MEMORY as u32 - 1;
+
+// Who fired a bullet, it only hurts the other team
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
+// How hard an enemy bullet pushes the player back, in pixels per second
+const BULLET_KNOCKBACK: f32 = 150.0;
+
+// How a bullet moves besides flying straight on. All of it is optional, the
+// default flies straight at a constant speed until it leaves the screen.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Motion {
+    #[serde(default)]
+    pub delay: f32, // Seconds it hangs still before moving
+    #[serde(default)]
+    pub acceleration: f32, // Pixels per second squared, slowing down stops it instead of turning it around
+    #[serde(default = "unlimited")]
+    pub max_speed: f32,
+    #[serde(default)]
+    pub turn_rate: f32, // Degrees per second it keeps turning, positive is clockwise
+    #[serde(default)]
+    pub homing: f32, // Degrees per second it can turn towards its target
+    #[serde(default)]
+    pub wave_amplitude: f32, // Pixels it sways to either side of its path
+    #[serde(default)]
+    pub wave_frequency: f32, // Sways per second
+    #[serde(default = "unlimited")]
+    pub lifetime: f32, // Seconds before it expires
+}
+
+fn unlimited() -> f32 {
+    f32::INFINITY
+}
+
+impl Default for Motion {
+    fn default() -> Motion {
+        Motion {
+            delay: 0.0,
+            acceleration: 0.0,
+            max_speed: f32::INFINITY,
+            turn_rate: 0.0,
+            homing: 0.0,
+            wave_amplitude: 0.0,
+            wave_frequency: 0.0,
+            lifetime: f32::INFINITY,
+        }
+    }
+}
+
+// Pattern a bullet bursts into when its lifetime runs out
+#[derive(Clone, Copy, Debug)]
+pub struct Burst {
+    pub pattern: usize,
+    pub stats: ShotStats,
+}
 
 pub struct Bullet {
+    pub team: Team,
     pub pos: na::Point2<f32>,
-    pub vel: na::Vector2<f32>,
+    pub prev_pos: na::Point2<f32>,
+    pub heading: f32, // Radians, 0 is to the right
+    pub speed: f32,
     pub damage: i32,
     pub size: f32,
+    pub motion: Motion,
+    pub age: f32, // Seconds since it was fired
+    pub wave_offset: f32, // How far the wave has pushed it off its path
+    pub on_expire: Option<Burst>,
+    pub pierce: u32, // Enemies it can still go through before it is used up
+    hits: [Option<Id>; HIT_MEMORY], // Enemies it already hurt
 }
 
 impl Bullet {
-    pub fn new(pos: na::Point2<f32>, target: na::Point2<f32>, speed: f32, damage: i32, size_of_bullet: f32) -> Bullet {
-        let direction = (target - pos).normalize();
-        let sp = speed;
+    // Speed is in pixels per second
+    pub fn new(team: Team, pos: na::Point2<f32>, target: na::Point2<f32>, speed: f32, damage: i32, size_of_bullet: f32) -> Bullet {
+        let direction = target - pos;
         Bullet {
+            team,
             pos,
-            vel: direction * sp,
+            prev_pos: pos,
+            heading: direction.y.atan2(direction.x),
+            speed,
             damage,
-            size: size_of_bullet
+            size: size_of_bullet,
+            motion: Motion::default(),
+            age: 0.0,
+            wave_offset: 0.0,
+            on_expire: None,
+            pierce: 0,
+            hits: [None; HIT_MEMORY],
+        }
+    }
+
+    pub fn with_motion(mut self, motion: Motion) -> Bullet {
+        self.motion = motion;
+        self
+    }
+
+    pub fn with_pierce(mut self, pierce: u32) -> Bullet {
+        self.pierce = pierce.min(MAX_PIERCE);
+        self
+    }
+
+    pub fn has_hit(&self, target: Id) -> bool {
+        self.hits.contains(&Some(target))
+    }
+
+    // Remembers the target, true when that used the bullet up
+    pub fn register_hit(&mut self, target: Id) -> bool {
+        if self.pierce == 0 {
+            return true;
+        }
+        self.pierce -= 1;
+        if let Some(slot) = self.hits.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(target);
+        }
+        false
+    }
+
+    // Moves the bullet one step, homing bullets turn towards `target`.
+    // Only needs the bullet itself, so trajectories can be traced without a window.
+    pub fn update(&mut self, dt: f32, target: Option<na::Point2<f32>>) {
+        self.prev_pos = self.pos;
+        self.age += dt;
+        let motion = self.motion;
+        if self.age <= motion.delay {
+            return;
+        }
+
+        self.speed = (self.speed + motion.acceleration * dt).max(0.0).min(motion.max_speed);
+        self.heading += motion.turn_rate.to_radians() * dt;
+        if let Some(target) = target.filter(|_| motion.homing > 0.0) {
+            let to_target = target - self.center();
+            let max_turn = motion.homing.to_radians() * dt;
+            self.heading += wrap_angle(to_target.y.atan2(to_target.x) - self.heading).clamp(-max_turn, max_turn);
+        }
+        let direction = na::Vector2::new(self.heading.cos(), self.heading.sin());
+        self.pos += direction * self.speed * dt;
+
+        // Sways across its direction, only the change since the last step is applied
+        if motion.wave_amplitude != 0.0 {
+            let offset = motion.wave_amplitude * (TAU * motion.wave_frequency * (self.age - motion.delay)).sin();
+            self.pos += na::Vector2::new(-direction.y, direction.x) * (offset - self.wave_offset);
+            self.wave_offset = offset;
         }
     }
 
-    pub fn update(&mut self) {
-        self.pos += self.vel;
+    pub fn is_expired(&self) -> bool {
+        self.age >= self.motion.lifetime
     }
 
-    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+    pub fn center(&self) -> na::Point2<f32> {
+        self.pos + na::Vector2::new(self.size / 2.0, self.size / 2.0)
+    }
+
+    pub fn draw(&self, renderer: &mut Renderer, alpha: f32) -> GameResult {
         // Draw bullet as square
-        let square = graphics::Rect::new(self.pos.x, self.pos.y, self.size, self.size);
-        let square_mesh = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            square,
-            Color::from_rgb(255, 0, 0),
-        )?;
-        graphics::draw(ctx, &square_mesh, DrawParam::default())?;
-        Ok(())
+        let pos = interpolate(&self.prev_pos, &self.pos, alpha);
+        let square = graphics::Rect::new(pos.x, pos.y, self.size, self.size);
+        renderer.rect(square, Color::from_rgb(255, 0, 0))
     }
 
     pub fn is_off_screen(&self) -> bool {
-        self.pos.x < 0.0 || self.pos.x > 1600.0 || self.pos.y < 0.0 || self.pos.y > 1100.0
+        !playfield::contains(&self.pos, playfield::BULLET_MARGIN)
+    }
+
+    // The drawn square, pos is its top-left corner
+    pub fn collider(&self) -> Collider {
+        Collider::aabb(self.size, self.size).with_offset(na::Vector2::new(self.size / 2.0, self.size / 2.0))
     }
 
-    pub fn check_collision_with_player(&self, player: &Player) -> bool {
-        let player_rect = graphics::Rect::new(player.player_pos.x - 10.0, player.player_pos.y - 10.0, 20.0, 20.0);
-        let bullet_rect = graphics::Rect::new(self.pos.x - 10.0, self.pos.y - 10.0, 20.0, 20.0);
-        bullet_rect.overlaps(&player_rect)
+    pub fn bounds(&self) -> graphics::Rect {
+        graphics::Rect::new(self.pos.x, self.pos.y, self.size, self.size)
     }
 
-    pub fn apply_damage(&self, player: &mut Player) {
-        player.take_damage(self.damage);
+    pub fn overlaps(&self, collider: &Collider, pos: &na::Point2<f32>) -> bool {
+        self.collider().overlaps(&self.pos, collider, pos)
+    }
+
+    // False when the player is invulnerable and the bullet flies on
+    pub fn apply_damage(&self, player: &mut Player) -> bool {
+        player.hit(self.damage, &self.center(), BULLET_KNOCKBACK)
     }
 
     pub fn apply_damage_to_enemy(&self, enemy: &mut dyn Enemy) -> i32{
@@ -59,11 +212,111 @@ impl Bullet {
         enemy.get_hp()
     }
 
-    pub fn check_collision_with_enemy(&self, enemy: &dyn Enemy) -> bool {
-        let enemy_pos = enemy.get_pos();
-        let enemy_rect = graphics::Rect::new(enemy_pos.x - 10.0, enemy_pos.y - 10.0, enemy.get_size(), enemy.get_size());
-        let bullet_rect = graphics::Rect::new(self.pos.x - 10.0, self.pos.y - 10.0, 20.0, 20.0);
-        bullet_rect.overlaps(&enemy_rect)
+}
+
+// An angle difference brought into -PI..PI, the short way round
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::FIXED_DT;
+
+    // A bullet at the origin flying right at `speed`
+    fn bullet(speed: f32, motion: Motion) -> Bullet {
+        let origin = na::Point2::origin();
+        Bullet::new(Team::Enemy, origin, origin + na::Vector2::x(), speed, 1, 10.0).with_motion(motion)
+    }
+
+    fn step(bullet: &mut Bullet, seconds: f32, target: Option<na::Point2<f32>>) {
+        for _ in 0..(seconds / FIXED_DT).round() as usize {
+            bullet.update(FIXED_DT, target);
+        }
+    }
+
+    #[test]
+    fn delay_holds_still_then_moves() {
+        let mut b = bullet(100.0, Motion { delay: 0.5, ..Motion::default() });
+        step(&mut b, 0.45, None);
+        assert_eq!(b.pos, na::Point2::origin());
+        step(&mut b, 0.5, None);
+        assert!(b.pos.x > 40.0 && b.pos.y.abs() < 1e-3);
     }
 
+    #[test]
+    fn slowing_down_stops_instead_of_turning_around() {
+        let mut b = bullet(100.0, Motion { acceleration: -300.0, ..Motion::default() });
+        let mut last_x = b.pos.x;
+        for _ in 0..120 {
+            b.update(FIXED_DT, None);
+            assert!(b.pos.x >= last_x);
+            last_x = b.pos.x;
+        }
+        assert_eq!(b.speed, 0.0);
+    }
+
+    #[test]
+    fn acceleration_is_capped_by_max_speed() {
+        let mut b = bullet(100.0, Motion { acceleration: 1000.0, max_speed: 250.0, ..Motion::default() });
+        for _ in 0..60 {
+            b.update(FIXED_DT, None);
+            assert!(b.speed <= 250.0);
+        }
+        assert_eq!(b.speed, 250.0);
+    }
+
+    #[test]
+    fn turn_rate_turns_clockwise() {
+        let mut b = bullet(100.0, Motion { turn_rate: 90.0, ..Motion::default() });
+        step(&mut b, 0.5, None);
+        assert!((b.heading - 45.0_f32.to_radians()).abs() < 1e-3);
+        // Screen y points down, so turning clockwise from the right goes down
+        assert!(b.pos.y > 0.0);
+    }
+
+    #[test]
+    fn homing_turns_at_most_its_rate_and_closes_in() {
+        let target = na::Point2::new(0.0, 300.0);
+        let mut b = bullet(100.0, Motion { homing: 90.0, ..Motion::default() });
+        let start_distance = na::distance(&b.center(), &target);
+        let max_turn = 90.0_f32.to_radians() * FIXED_DT;
+        for _ in 0..120 {
+            let heading = b.heading;
+            b.update(FIXED_DT, Some(target));
+            assert!(wrap_angle(b.heading - heading).abs() <= max_turn + 1e-5);
+        }
+        assert!(na::distance(&b.center(), &target) < start_distance - 100.0);
+    }
+
+    #[test]
+    fn wave_stays_within_its_amplitude() {
+        let mut b = bullet(100.0, Motion { wave_amplitude: 20.0, wave_frequency: 2.0, ..Motion::default() });
+        let mut widest: f32 = 0.0;
+        for tick in 1..=60 {
+            b.update(FIXED_DT, None);
+            assert!(b.pos.y.abs() <= 20.0 + 1e-3);
+            widest = widest.max(b.pos.y.abs());
+            // The sway doesn't pile up along the path
+            assert!((b.pos.x - 100.0 * FIXED_DT * tick as f32).abs() < 1e-3);
+        }
+        assert!(widest > 19.0);
+        // Back on the path after whole sways
+        assert!(b.pos.y.abs() < 1e-2);
+    }
+
+    #[test]
+    fn expires_at_its_lifetime() {
+        let mut b = bullet(100.0, Motion { lifetime: 1.0, ..Motion::default() });
+        let mut ticks = 0;
+        while !b.is_expired() {
+            b.update(FIXED_DT, None);
+            ticks += 1;
+            assert!(ticks <= 61);
+        }
+        // Expired on the first step that reached the lifetime, not later
+        assert!(b.age >= 1.0 && b.age - FIXED_DT < 1.0);
+        assert!(!bullet(100.0, Motion::default()).is_expired());
+    }
 }
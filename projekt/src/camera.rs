@@ -0,0 +1,63 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use nalgebra as na;
+
+use crate::playfield;
+
+// Maps the playfield onto the window: scaled as large as it fits without
+// stretching and centered, with black bars filling the rest
+pub struct Camera {
+    view: Rect, // Part of the world the whole window shows
+    scale: f32, // Window pixels per world unit
+}
+
+impl Camera {
+    pub fn new(window_w: f32, window_h: f32) -> Camera {
+        let mut camera = Camera { view: playfield::rect(), scale: 1.0 };
+        camera.resize(window_w, window_h);
+        camera
+    }
+
+    pub fn resize(&mut self, window_w: f32, window_h: f32) {
+        self.scale = (window_w / playfield::WIDTH).min(window_h / playfield::HEIGHT);
+        let (view_w, view_h) = (window_w / self.scale, window_h / self.scale);
+        self.view = Rect::new(
+            (playfield::WIDTH - view_w) / 2.0,
+            (playfield::HEIGHT - view_h) / 2.0,
+            view_w,
+            view_h,
+        );
+    }
+
+    // Makes everything drawn afterwards use world coordinates
+    pub fn apply(&self, ctx: &mut Context) -> GameResult {
+        graphics::set_screen_coordinates(ctx, self.view)
+    }
+
+    pub fn screen_to_world(&self, screen: na::Point2<f32>) -> na::Point2<f32> {
+        na::Point2::new(self.view.x + screen.x / self.scale, self.view.y + screen.y / self.scale)
+    }
+
+    // Covers whatever is drawn outside the playfield
+    pub fn draw_letterbox(&self, ctx: &mut Context) -> GameResult {
+        let (view, field) = (self.view, playfield::rect());
+        let bars = [
+            Rect::new(view.x, view.y, field.x - view.x, view.h), // Left
+            Rect::new(field.right(), view.y, view.right() - field.right(), view.h), // Right
+            Rect::new(view.x, view.y, view.w, field.y - view.y), // Top
+            Rect::new(view.x, field.bottom(), view.w, view.bottom() - field.bottom()), // Bottom
+        ];
+
+        let mut builder = MeshBuilder::new();
+        let mut any = false;
+        for bar in bars.iter().filter(|bar| bar.w > 0.0 && bar.h > 0.0) {
+            builder.rectangle(DrawMode::fill(), *bar, Color::BLACK)?;
+            any = true;
+        }
+        if any {
+            let mesh = builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use ggez::{GameError, GameResult};
+use serde::Deserialize;
+
+use crate::boss::BossScript;
+use crate::pattern::PatternLibrary;
+use crate::collider::{Collider, ShapeKind, MAX_POINTS};
+use crate::resources::{image_size, load_toml};
+
+// A stat that grows with the level: base + per_level * (level - 1)
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub struct Scaled {
+    pub base: f32,
+    #[serde(default)]
+    pub per_level: f32,
+}
+
+impl Scaled {
+    pub fn at(&self, level: i32) -> f32 {
+        self.base + self.per_level * (level - 1) as f32
+    }
+
+    pub fn at_i32(&self, level: i32) -> i32 {
+        self.at(level).round() as i32
+    }
+}
+
+// Which code drives an archetype
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Behaviour {
+    Chaser,  // Runs into the player (TriangleEnemy)
+    Shooter, // Walks towards the player and shoots (HexagonEnemy)
+    Boss,    // Boss attack patterns, only one alive at a time
+}
+
+// One enemy archetype as written in resources/enemies.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyDef {
+    pub name: String,
+    pub behaviour: Behaviour,
+    pub sprite: String,
+    pub sprite_scale: f32,
+    pub collider: ShapeKind,
+    pub hp: Scaled,
+    pub speed: Scaled,
+    pub damage: Scaled,
+    pub coins: Scaled,
+    pub points: Scaled,
+    #[serde(default)]
+    pub bullet_speed: Scaled,
+    #[serde(default)]
+    pub bullet_size: f32,
+    #[serde(default)]
+    pub shoot_cooldown: f32,
+    // Pattern from patterns.toml a shooter fires every shoot_cooldown
+    pub pattern: Option<String>,
+    #[serde(skip)]
+    pub pattern_index: Option<usize>,
+    #[serde(default = "default_spawn_weight")]
+    pub spawn_weight: u32,
+    #[serde(default = "default_spawn_chance")]
+    pub spawn_chance: f32,
+    #[serde(default = "default_contact_cooldown")]
+    pub contact_cooldown: f32,
+    #[serde(default = "default_knockback")]
+    pub knockback: f32,
+    #[serde(default = "default_bomb_drop_chance")]
+    pub bomb_drop_chance: f32,
+    // Drawn sprite size in pixels, read from the image when loading
+    #[serde(skip)]
+    pub size: [f32; 2],
+}
+
+impl EnemyDef {
+    // Hitbox matching the sprite as it is drawn
+    pub fn collider(&self) -> Collider {
+        Collider::for_sprite(self.collider, self.size[0], self.size[1])
+    }
+}
+
+fn default_spawn_weight() -> u32 {
+    1
+}
+
+fn default_spawn_chance() -> f32 {
+    1.0
+}
+
+fn default_contact_cooldown() -> f32 {
+    1.0
+}
+
+fn default_knockback() -> f32 {
+    400.0
+}
+
+fn default_bomb_drop_chance() -> f32 {
+    0.03
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyCatalogue {
+    pub enemies: Vec<EnemyDef>,
+    // Bullet patterns enemies fire, from resources/patterns.toml
+    #[serde(skip)]
+    pub patterns: PatternLibrary,
+    // Attacks and phases every boss uses, from resources/boss.toml
+    #[serde(skip)]
+    pub boss_script: BossScript,
+}
+
+impl EnemyCatalogue {
+    pub fn load() -> GameResult<EnemyCatalogue> {
+        let mut catalogue: EnemyCatalogue = load_toml("enemies.toml")?;
+        for def in &mut catalogue.enemies {
+            let [w, h] = image_size(&def.sprite)?;
+            def.size = [w * def.sprite_scale, h * def.sprite_scale];
+        }
+
+        let mut catalogue = catalogue.resolve(PatternLibrary::load()?)?;
+        catalogue.boss_script = BossScript::load(&catalogue.patterns)?;
+        Ok(catalogue)
+    }
+
+    // Checks the archetypes and turns their pattern names into indices
+    fn resolve(mut self, patterns: PatternLibrary) -> GameResult<EnemyCatalogue> {
+        if self.total_spawn_weight() == 0 {
+            return Err(GameError::ResourceLoadError("resources/enemies.toml: no enemy can spawn".to_string()));
+        }
+
+        self.patterns = patterns;
+        for def in &mut self.enemies {
+            if let Some(pattern) = &def.pattern {
+                def.pattern_index = Some(self.patterns.find(pattern).ok_or_else(|| GameError::ResourceLoadError(
+                    format!("resources/enemies.toml: {} fires unknown pattern {}", def.name, pattern)))?);
+            }
+        }
+
+        // Catch stats that would break the behaviour code
+        for def in &self.enemies {
+            if def.collider.corners() > MAX_POINTS {
+                return Err(GameError::ResourceLoadError(format!(
+                    "resources/enemies.toml: {} has a collider with more than {} corners", def.name, MAX_POINTS)));
+            }
+            if def.behaviour == Behaviour::Shooter && (def.shoot_cooldown <= 0.0 || def.pattern.is_none()) {
+                return Err(GameError::ResourceLoadError(format!(
+                    "resources/enemies.toml: {} needs a positive shoot_cooldown and a pattern", def.name)));
+            }
+            if def.behaviour != Behaviour::Chaser && def.bullet_speed.base <= 0.0 {
+                return Err(GameError::ResourceLoadError(format!(
+                    "resources/enemies.toml: {} needs a positive bullet_speed", def.name)));
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn total_spawn_weight(&self) -> u32 {
+        self.enemies.iter().map(|def| def.spawn_weight).sum()
+    }
+
+    // Index of the archetype a roll in 0..total_spawn_weight lands on
+    pub fn pick(&self, roll: u32) -> usize {
+        let mut remaining = roll;
+        for (i, def) in self.enemies.iter().enumerate() {
+            if remaining < def.spawn_weight {
+                return i;
+            }
+            remaining -= def.spawn_weight;
+        }
+        self.enemies.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resolves `enemies` against a single "aimed" pattern, so nothing depends on resources/
+    fn catalogue(enemies: &str) -> GameResult<EnemyCatalogue> {
+        let patterns: PatternLibrary = toml::from_str(r#"
+            [[patterns]]
+            id = "ring"
+            shape = "ring"
+
+            [[patterns]]
+            id = "aimed"
+            shape = "fan"
+        "#).unwrap();
+        let catalogue: EnemyCatalogue = toml::from_str(enemies).unwrap();
+        catalogue.resolve(patterns)
+    }
+
+    fn load_error(enemies: &str) -> String {
+        match catalogue(enemies) {
+            Err(GameError::ResourceLoadError(message)) => message,
+            Err(other) => panic!("wrong error: {:?}", other),
+            Ok(_) => panic!("loaded a broken catalogue"),
+        }
+    }
+
+    // A shooter with `extra` fields added or overriding the defaults below
+    fn shooter(extra: &str) -> String {
+        format!(r#"
+            [[enemies]]
+            name = "hexagon"
+            behaviour = "shooter"
+            sprite = "/hexagon.png"
+            sprite_scale = 1.0
+            collider = "hexagon"
+            hp = {{ base = 3 }}
+            speed = {{ base = 100 }}
+            damage = {{ base = 1 }}
+            coins = {{ base = 10 }}
+            points = {{ base = 20 }}
+            {}
+        "#, extra)
+    }
+
+    #[test]
+    fn pattern_names_become_indices() {
+        let catalogue = catalogue(&shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+            pattern = "aimed"
+        "#)).unwrap();
+        assert_eq!(catalogue.enemies[0].pattern_index, Some(1));
+        assert_eq!(catalogue.patterns.patterns.len(), 2);
+    }
+
+    #[test]
+    fn unknown_pattern_is_refused() {
+        let message = load_error(&shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+            pattern = "aimd"
+        "#));
+        assert_eq!(message, "resources/enemies.toml: hexagon fires unknown pattern aimd");
+    }
+
+    #[test]
+    fn shooters_need_a_pattern_cooldown_and_bullet_speed() {
+        let message = load_error(&shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+        "#));
+        assert_eq!(message, "resources/enemies.toml: hexagon needs a positive shoot_cooldown and a pattern");
+
+        let message = load_error(&shooter(r#"
+            bullet_speed = { base = 300 }
+            pattern = "aimed"
+        "#));
+        assert_eq!(message, "resources/enemies.toml: hexagon needs a positive shoot_cooldown and a pattern");
+
+        let message = load_error(&shooter(r#"
+            shoot_cooldown = 2.0
+            pattern = "aimed"
+        "#));
+        assert_eq!(message, "resources/enemies.toml: hexagon needs a positive bullet_speed");
+    }
+
+    #[test]
+    fn something_has_to_spawn() {
+        let message = load_error(&shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+            pattern = "aimed"
+            spawn_weight = 0
+        "#));
+        assert_eq!(message, "resources/enemies.toml: no enemy can spawn");
+    }
+
+    #[test]
+    fn rolls_land_by_spawn_weight() {
+        let catalogue = catalogue(&(shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+            pattern = "aimed"
+            spawn_weight = 3
+        "#) + &shooter(r#"
+            bullet_speed = { base = 300 }
+            shoot_cooldown = 2.0
+            pattern = "ring"
+        "#))).unwrap();
+
+        assert_eq!(catalogue.total_spawn_weight(), 4);
+        let picks: Vec<usize> = (0..4).map(|roll| catalogue.pick(roll)).collect();
+        assert_eq!(picks, vec![0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use ggez::{GameError, GameResult};
+
+// Command line options
+//   --seed <n>        seed for the run's random generator (random if not given)
+//   --simulate <n>    run n frames without opening a window
+//   --record <file>   where to save the replay of this run
+//   --replay <file>   play a saved replay instead of reading the keyboard
+//   --endless         random endless spawning instead of the wave script
+//   --bench           time the simulation with 5000 bullets and 500 enemies
+//   --pattern <id>    print the bullets of one pattern from patterns.toml as
+//                     CSV, for at most --simulate frames (600 if not given)
+//   --level <n>       enemy level the pattern is fired at (1 if not given)
+pub struct Options {
+    pub seed: u64,
+    pub simulate: Option<u64>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub endless: bool,
+    pub bench: bool,
+    pub pattern: Option<String>,
+    pub level: i32,
+}
+
+impl Options {
+    // A missing or malformed value is an error instead of a panic
+    pub fn parse() -> GameResult<Options> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let mut options = Options {
+            seed: rand::random(),
+            simulate: None,
+            record: None,
+            replay: None,
+            endless: false,
+            bench: false,
+            pattern: None,
+            level: 1,
+        };
+
+        let error = |message: &str| GameError::ConfigError(message.to_string());
+        let mut i = 0;
+        while i < args.len() {
+            let value = args.get(i + 1);
+            match args[i].as_str() {
+                "--seed" => {
+                    options.seed = value.and_then(|n| n.parse().ok()).ok_or_else(|| error("--seed expects a number"))?;
+                    i += 1;
+                }
+                "--simulate" => {
+                    let frames = value.and_then(|n| n.parse().ok());
+                    if frames.is_some() {
+                        i += 1;
+                    }
+                    options.simulate = Some(frames.unwrap_or(3600));
+                }
+                "--record" => {
+                    options.record = Some(value.ok_or_else(|| error("--record expects a file"))?.into());
+                    i += 1;
+                }
+                "--replay" => {
+                    options.replay = Some(value.ok_or_else(|| error("--replay expects a file"))?.into());
+                    i += 1;
+                }
+                "--pattern" => {
+                    options.pattern = Some(value.ok_or_else(|| error("--pattern expects a pattern id"))?.clone());
+                    i += 1;
+                }
+                "--level" => {
+                    options.level = value.and_then(|n| n.parse().ok()).ok_or_else(|| error("--level expects a number"))?;
+                    i += 1;
+                }
+                "--endless" => options.endless = true,
+                "--bench" => options.bench = true,
+                other => println!("Unknown argument: {}", other),
+            }
+            i += 1;
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,334 @@
+use std::f32::consts::PI;
+
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, Rect};
+use nalgebra as na;
+use serde::Deserialize;
+
+// Most corners a polygon collider can have, so overlap tests can keep the
+// corners on the stack instead of allocating
+pub const MAX_POINTS: usize = 8;
+type Corners = [na::Point2<f32>; MAX_POINTS];
+
+// Hitbox shape, with the center placed at `offset` from the entity position
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Circle { radius: f32 },
+    Aabb { half_w: f32, half_h: f32 },
+    Polygon { points: Vec<na::Vector2<f32>> }, // Convex, around the center
+}
+
+// Shape names used in resources/enemies.toml
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeKind {
+    Circle,
+    Box,
+    Triangle,
+    Hexagon,
+}
+
+impl ShapeKind {
+    // Corners of the collider it makes, 0 for circles
+    pub fn corners(self) -> usize {
+        match self {
+            ShapeKind::Circle => 0,
+            ShapeKind::Box => 4,
+            ShapeKind::Triangle => 3,
+            ShapeKind::Hexagon => 6,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Collider {
+    pub shape: Shape,
+    pub offset: na::Vector2<f32>,
+}
+
+impl Collider {
+    pub fn circle(radius: f32) -> Collider {
+        Collider { shape: Shape::Circle { radius }, offset: na::Vector2::zeros() }
+    }
+
+    pub fn aabb(w: f32, h: f32) -> Collider {
+        Collider { shape: Shape::Aabb { half_w: w / 2.0, half_h: h / 2.0 }, offset: na::Vector2::zeros() }
+    }
+
+    // Regular polygon with a corner pointing up. The catalogue turns down
+    // shapes with more than MAX_POINTS corners when it loads.
+    pub fn regular_polygon(sides: usize, radius: f32) -> Collider {
+        debug_assert!(sides <= MAX_POINTS, "polygon colliders have at most {} sides", MAX_POINTS);
+        let points = (0..sides)
+            .map(|i| {
+                let angle = -PI / 2.0 + i as f32 * 2.0 * PI / sides as f32;
+                na::Vector2::new(angle.cos() * radius, angle.sin() * radius)
+            })
+            .collect();
+        Collider { shape: Shape::Polygon { points }, offset: na::Vector2::zeros() }
+    }
+
+    // Collider filling a sprite of `w` x `h` pixels drawn from its top-left corner
+    pub fn for_sprite(kind: ShapeKind, w: f32, h: f32) -> Collider {
+        let collider = match kind {
+            ShapeKind::Circle => Collider::circle(w.min(h) / 2.0),
+            ShapeKind::Box => Collider::aabb(w, h),
+            ShapeKind::Triangle => Collider::regular_polygon(3, w.min(h) / 2.0),
+            ShapeKind::Hexagon => Collider::regular_polygon(6, w.min(h) / 2.0),
+        };
+        collider.with_offset(na::Vector2::new(w / 2.0, h / 2.0))
+    }
+
+    pub fn with_offset(mut self, offset: na::Vector2<f32>) -> Collider {
+        self.offset = offset;
+        self
+    }
+
+    pub fn center(&self, pos: &na::Point2<f32>) -> na::Point2<f32> {
+        pos + self.offset
+    }
+
+    pub fn bounds(&self, pos: &na::Point2<f32>) -> Rect {
+        let c = self.center(pos);
+        match &self.shape {
+            Shape::Circle { radius } => Rect::new(c.x - radius, c.y - radius, radius * 2.0, radius * 2.0),
+            Shape::Aabb { half_w, half_h } => Rect::new(c.x - half_w, c.y - half_h, half_w * 2.0, half_h * 2.0),
+            Shape::Polygon { points } => {
+                let (mut min, mut max) = (na::Vector2::repeat(f32::MAX), na::Vector2::repeat(f32::MIN));
+                for p in points {
+                    min = min.inf(p);
+                    max = max.sup(p);
+                }
+                Rect::new(c.x + min.x, c.y + min.y, max.x - min.x, max.y - min.y)
+            }
+        }
+    }
+
+    // Corners in world space written to `out`, boxes count as polygons and circles have none
+    fn world_points<'a>(&self, pos: &na::Point2<f32>, out: &'a mut Corners) -> &'a [na::Point2<f32>] {
+        let c = self.center(pos);
+        let count = match &self.shape {
+            Shape::Circle { .. } => 0,
+            Shape::Aabb { half_w, half_h } => {
+                out[0] = c + na::Vector2::new(-half_w, -half_h);
+                out[1] = c + na::Vector2::new(*half_w, -half_h);
+                out[2] = c + na::Vector2::new(*half_w, *half_h);
+                out[3] = c + na::Vector2::new(-half_w, *half_h);
+                4
+            }
+            Shape::Polygon { points } => {
+                for (corner, p) in out.iter_mut().zip(points) {
+                    *corner = c + p;
+                }
+                points.len()
+            }
+        };
+        &out[..count]
+    }
+
+    pub fn overlaps(&self, pos: &na::Point2<f32>, other: &Collider, other_pos: &na::Point2<f32>) -> bool {
+        let (mut own, mut others) = ([na::Point2::origin(); MAX_POINTS], [na::Point2::origin(); MAX_POINTS]);
+        match (&self.shape, &other.shape) {
+            (Shape::Circle { radius: r1 }, Shape::Circle { radius: r2 }) => {
+                na::distance_squared(&self.center(pos), &other.center(other_pos)) < (r1 + r2) * (r1 + r2)
+            }
+            (Shape::Aabb { .. }, Shape::Aabb { .. }) => self.bounds(pos).overlaps(&other.bounds(other_pos)),
+            (Shape::Circle { radius }, _) => {
+                circle_polygon(&self.center(pos), *radius, other.world_points(other_pos, &mut others))
+            }
+            (_, Shape::Circle { radius }) => {
+                circle_polygon(&other.center(other_pos), *radius, self.world_points(pos, &mut own))
+            }
+            _ => polygons_overlap(self.world_points(pos, &mut own), other.world_points(other_pos, &mut others)),
+        }
+    }
+
+    // How far along the ray from `origin` in `direction` (unit length) it enters
+    // the shape, None when it misses within `length`
+    pub fn ray_hit(&self, pos: &na::Point2<f32>, origin: &na::Point2<f32>, direction: &na::Vector2<f32>, length: f32) -> Option<f32> {
+        let c = self.center(pos);
+        if let Shape::Circle { radius } = &self.shape {
+            let from_center = origin - c;
+            let inside = from_center.norm_squared() - radius * radius;
+            if inside <= 0.0 {
+                return Some(0.0);
+            }
+            let b = from_center.dot(direction);
+            let discriminant = b * b - inside;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let t = -b - discriminant.sqrt();
+            return (t >= 0.0 && t <= length).then_some(t);
+        }
+
+        // Cuts the ray down to the part inside every edge of the polygon
+        let mut corners = [na::Point2::origin(); MAX_POINTS];
+        let points = self.world_points(pos, &mut corners);
+        let (mut enter, mut exit) = (0.0_f32, length);
+        for (i, normal) in axes(points).enumerate() {
+            // Pointing out of the shape whichever way the corners wind
+            let normal = if normal.dot(&(points[i] - c)) < 0.0 { -normal } else { normal };
+            let facing = normal.dot(direction);
+            let distance = normal.dot(&(points[i] - origin));
+            if facing > 0.0 {
+                exit = exit.min(distance / facing);
+            } else if facing < 0.0 {
+                enter = enter.max(distance / facing);
+            } else if distance < 0.0 {
+                return None;
+            }
+            if enter > exit {
+                return None;
+            }
+        }
+        Some(enter)
+    }
+
+    // Outline for the hitbox debug view
+    pub fn draw_outline(&self, ctx: &mut Context, pos: &na::Point2<f32>, color: Color) -> GameResult {
+        let c = self.center(pos);
+        let mesh = match &self.shape {
+            Shape::Circle { radius } => Mesh::new_circle(ctx, DrawMode::stroke(1.0), [c.x, c.y], *radius, 0.5, color)?,
+            Shape::Aabb { .. } => Mesh::new_rectangle(ctx, DrawMode::stroke(1.0), self.bounds(pos), color)?,
+            Shape::Polygon { points } => {
+                let points: Vec<[f32; 2]> = points.iter().map(|p| [c.x + p.x, c.y + p.y]).collect();
+                Mesh::new_polygon(ctx, DrawMode::stroke(1.0), &points, color)?
+            }
+        };
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+}
+
+fn project(points: &[na::Point2<f32>], axis: &na::Vector2<f32>) -> (f32, f32) {
+    points.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+        let d = p.coords.dot(axis);
+        (min.min(d), max.max(d))
+    })
+}
+
+// Edge normals of a convex polygon
+fn axes(points: &[na::Point2<f32>]) -> impl Iterator<Item = na::Vector2<f32>> + '_ {
+    (0..points.len()).map(move |i| {
+        let edge = points[(i + 1) % points.len()] - points[i];
+        na::Vector2::new(-edge.y, edge.x)
+    })
+}
+
+// Separating axis test for two convex polygons
+fn polygons_overlap(a: &[na::Point2<f32>], b: &[na::Point2<f32>]) -> bool {
+    axes(a).chain(axes(b)).all(|axis| {
+        let (a_min, a_max) = project(a, &axis);
+        let (b_min, b_max) = project(b, &axis);
+        a_min < b_max && b_min < a_max
+    })
+}
+
+// Separating axis test for a circle and a convex polygon
+fn circle_polygon(center: &na::Point2<f32>, radius: f32, points: &[na::Point2<f32>]) -> bool {
+    let closest = match points.iter().min_by(|a, b| na::distance_squared(a, center).total_cmp(&na::distance_squared(b, center))) {
+        Some(p) => *p,
+        None => return false,
+    };
+
+    axes(points).chain(std::iter::once(closest - center)).all(|axis| {
+        let length = axis.norm();
+        if length == 0.0 {
+            return true;
+        }
+        let axis = axis / length;
+        let (p_min, p_max) = project(points, &axis);
+        let c = center.coords.dot(&axis);
+        c - radius < p_max && p_min < c + radius
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> na::Point2<f32> {
+        na::Point2::new(x, y)
+    }
+
+    fn overlap(a: &Collider, a_pos: na::Point2<f32>, b: &Collider, b_pos: na::Point2<f32>) -> bool {
+        let result = a.overlaps(&a_pos, b, &b_pos);
+        assert_eq!(result, b.overlaps(&b_pos, a, &a_pos), "overlap should not depend on the order");
+        result
+    }
+
+    #[test]
+    fn circles() {
+        let circle = Collider::circle(10.0);
+        assert!(overlap(&circle, at(0.0, 0.0), &circle, at(19.0, 0.0)));
+        assert!(!overlap(&circle, at(0.0, 0.0), &circle, at(21.0, 0.0)));
+        assert!(overlap(&circle, at(0.0, 0.0), &Collider::circle(2.0), at(3.0, 3.0)));
+    }
+
+    #[test]
+    fn boxes() {
+        let square = Collider::aabb(20.0, 20.0);
+        assert!(overlap(&square, at(0.0, 0.0), &square, at(19.0, 19.0)));
+        assert!(!overlap(&square, at(0.0, 0.0), &square, at(21.0, 0.0)));
+        assert!(overlap(&square, at(0.0, 0.0), &Collider::aabb(4.0, 4.0), at(2.0, -3.0)));
+    }
+
+    #[test]
+    fn polygons() {
+        let hexagon = Collider::regular_polygon(6, 10.0);
+        let triangle = Collider::regular_polygon(3, 10.0);
+        // The hexagon's flat sides are sqrt(3) / 2 * 10 from its middle
+        assert!(overlap(&hexagon, at(0.0, 0.0), &hexagon, at(17.0, 0.0)));
+        assert!(!overlap(&hexagon, at(0.0, 0.0), &hexagon, at(18.0, 0.0)));
+        // Corner to corner along the y axis
+        assert!(overlap(&hexagon, at(0.0, 0.0), &hexagon, at(0.0, 19.0)));
+        assert!(!overlap(&hexagon, at(0.0, 0.0), &hexagon, at(0.0, 21.0)));
+        assert!(overlap(&hexagon, at(0.0, 0.0), &Collider::regular_polygon(3, 2.0), at(1.0, 1.0)));
+        // The triangle's corner points up into the hexagon's bottom corner
+        assert!(overlap(&hexagon, at(0.0, 0.0), &triangle, at(0.0, 19.0)));
+        assert!(!overlap(&hexagon, at(0.0, 0.0), &triangle, at(0.0, 21.0)));
+        // Separated only along an edge normal of the triangle, not along an axis
+        assert!(!overlap(&triangle, at(0.0, 0.0), &Collider::aabb(2.0, 2.0), at(8.0, -4.0)));
+    }
+
+    #[test]
+    fn circle_and_polygon() {
+        let circle = Collider::circle(5.0);
+        let square = Collider::aabb(20.0, 20.0);
+        assert!(overlap(&circle, at(14.0, 0.0), &square, at(0.0, 0.0)));
+        assert!(!overlap(&circle, at(16.0, 0.0), &square, at(0.0, 0.0)));
+        // Off the corner: inside both axes' projections but not touching
+        assert!(!overlap(&circle, at(14.0, 14.0), &square, at(0.0, 0.0)));
+        assert!(overlap(&circle, at(13.0, 13.0), &square, at(0.0, 0.0)));
+        // Either one inside the other
+        assert!(overlap(&circle, at(1.0, 1.0), &square, at(0.0, 0.0)));
+        assert!(overlap(&Collider::circle(50.0), at(0.0, 0.0), &Collider::regular_polygon(6, 10.0), at(5.0, 5.0)));
+    }
+
+    #[test]
+    fn offsets_move_the_shape() {
+        let sprite = Collider::for_sprite(ShapeKind::Box, 20.0, 20.0);
+        let circle = Collider::circle(5.0);
+        assert_eq!(sprite.center(&at(0.0, 0.0)), at(10.0, 10.0));
+        assert!(overlap(&sprite, at(0.0, 0.0), &circle, at(20.0, 10.0)));
+        assert!(!overlap(&sprite, at(0.0, 0.0), &circle, at(-6.0, 10.0)));
+    }
+
+    #[test]
+    fn rays() {
+        let right = na::Vector2::x();
+        let origin = at(0.0, 0.0);
+        for collider in [Collider::circle(10.0), Collider::aabb(20.0, 20.0), Collider::regular_polygon(6, 10.0)] {
+            let enter = collider.ray_hit(&at(50.0, 0.0), &origin, &right, 100.0)
+                .unwrap_or_else(|| panic!("{:?} was missed", collider.shape));
+            // The hexagon's flat sides face left and right
+            let near_side = if matches!(collider.shape, Shape::Polygon { .. }) { 50.0 - 75f32.sqrt() } else { 40.0 };
+            assert!((enter - near_side).abs() < 1e-3, "{:?} entered at {}", collider.shape, enter);
+
+            assert_eq!(collider.ray_hit(&at(50.0, 30.0), &origin, &right, 100.0), None, "{:?} above the ray", collider.shape);
+            assert_eq!(collider.ray_hit(&at(50.0, 0.0), &origin, &right, 30.0), None, "{:?} past the end", collider.shape);
+            assert_eq!(collider.ray_hit(&at(-50.0, 0.0), &origin, &right, 100.0), None, "{:?} behind", collider.shape);
+            assert_eq!(collider.ray_hit(&at(2.0, 1.0), &origin, &right, 100.0), Some(0.0), "{:?} around", collider.shape);
+        }
+    }
+}
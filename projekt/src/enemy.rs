@@ -1,22 +1,40 @@
-use ggez::{Context, GameResult};
+use ggez::GameResult;
+use ggez::graphics::Rect;
 use nalgebra as na;
 use crate::player::Player;
-use crate::bullet::Bullet;
+use crate::bullet::BulletPool;
+use crate::catalogue::EnemyCatalogue;
+use crate::render::Renderer;
+use crate::world::GameRng;
+use crate::collider::Collider;
 
+// Enemies of one archetype share its catalogue entry, patterns and boss script
 pub trait Enemy {
-    fn update(&mut self, player: &Player, ctx: &mut Context, game_bullets: &mut Vec<Bullet>);
-    fn draw(&self, ctx: &mut Context) -> GameResult;
-    fn check_collision(&self, player: &Player) -> bool;
-    fn apply_damage(&self, player: &mut Player);
+    fn update(&mut self, player: &Player, dt: f32, game_bullets: &mut BulletPool, catalogue: &EnemyCatalogue, rng: &mut GameRng);
+    // alpha is how far rendering is between the previous and the current update
+    fn draw(&self, catalogue: &EnemyCatalogue, renderer: &mut Renderer, alpha: f32) -> GameResult;
+    // Damage dealt by touching the player
+    fn contact_damage(&self) -> i32;
     fn take_damage(&mut self, damage: i32) -> i32;
 
     fn is_boss(&self) -> bool {
         false
     }
 
+    // Index in the enemy catalogue
+    fn archetype(&self) -> usize;
     fn get_pos(&self) -> &na::Point2<f32>;
     fn get_hp(&self) -> i32;
     fn get_coins(&self) -> i32;
     fn get_points(&self) -> i32;
-    fn get_size(&self) -> f32;
+    fn collider(&self) -> &Collider;
+
+    fn check_collision(&self, player: &Player) -> bool {
+        self.collider().overlaps(self.get_pos(), &player.collider, &player.player_pos)
+    }
+
+    // Rect around the hitbox, for the broadphase grid
+    fn bounds(&self) -> Rect {
+        self.collider().bounds(self.get_pos())
+    }
 }
\ No newline at end of file
@@ -1,328 +1,665 @@
-use ggez::{Context, GameResult};
-use ggez::event::{EventHandler};
-use ggez::graphics::{self, DrawParam, Image};
-
-use ggez::input::{keyboard};
-
-use crate::player::Player;
-use crate::enemy::Enemy;
-use crate::menu::MenuParallax;
-
-use crate::triangle::TriangleEnemy;
-use crate::hexagonal::HexagonEnemy;
-use crate::boss::Boss;
+use std::path::PathBuf;
 
-use crate::bullet::Bullet;
-use crate::shop::Shop;
-
-use rand::Rng;
+use ggez::{Context, GameResult};
+use ggez::conf::FullscreenType;
+use ggez::event::{Button, EventHandler, GamepadId, KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, DrawParam};
+use ggez::input::mouse;
 use nalgebra as na;
 
-#[derive(PartialEq)]
-pub enum GameState {
-    Playing,
-    GameOver,
-    Menu
-}
-
+use crate::menu::MenuParallax;
+use crate::screen::{self, Screen};
+use crate::bindings::{Action, Binding, Bindings, Deadzones, BINDINGS_FILE};
+use crate::input::{Aim, InputState, WeaponSwitch};
+use crate::replay::{Replay, ReplayFrame};
+use crate::sprites::Sprites;
+use crate::render::Renderer;
+use crate::camera::Camera;
+use crate::cli::Options;
+use crate::waves::WavePhase;
+use crate::world::{World, FIXED_DT, TICK_RATE};
+use crate::bullet::BulletPool;
+use crate::alloc_counter::allocations;
+
+// ggez front end: reads input from the Context, steps the World and draws it
 pub struct Game {
-    pub player: Player,
-    pub shop: Shop,
-    pub enemies: Vec<Box<dyn Enemy>>,
-    pub bullets: Vec<Bullet>,
-    pub is_boss: bool,
-    pub level: i32,
-    pub killed_enemies: i32,
-    pub spawn_rate: f32,
-    pub triangle_image: Image,
-    pub hexagon_image: Image,
-    pub boss_image: Image,
-    pub _player_image: Image,
-    pub tlo: Image,
-    pub game_state: GameState,
-    pub menu: MenuParallax
+    pub world: World,
+    pub input: InputState,
+    pub aim: Aim, // Last mouse position or right stick direction, whichever moved last
+    pub sprites: Sprites,
+    pub renderer: Renderer,
+    pub camera: Camera,
+    pub screens: Vec<Screen>, // Never empty, the last one is active
+    pub selected: usize, // Highlighted option of the active screen
+    pub held: InputState, // Buttons down when the screen last changed
+    pub bindings: Bindings,
+    pub bindings_error: Option<String>, // Why BINDINGS_FILE didn't load, nothing is saved over it then
+    pub rebinding: Option<Action>, // Waiting for a key to bind on the controls screen
+    pub confirming: Option<usize>, // Upgrade waiting for a yes on the shop screen
+    pub weapon_switch: Option<WeaponSwitch>, // Pressed since the last step, goes into its input
+    pub menu: MenuParallax,
+    pub recording: Replay,
+    pub record_path: PathBuf,
+    pub playback: Option<std::vec::IntoIter<ReplayFrame>>,
+    pub show_hitboxes: bool,
+    pub show_stats: bool,
+    pub fullscreen: bool,
+    pub tick_allocations: u64, // Allocations made by the last World::update
 }
 
 
 impl Game {
-    pub fn new(ctx: &mut Context) -> GameResult<Game> {
-        let _player_image = Image::new(ctx, "/player.png")?;
-        let player = Player::new(_player_image.clone())?;
-        let shop = Shop::new()?;
-        let enemies = Vec::new();
-        let bullets = Vec::new();
-        let is_boss = false;
-        let level = 1;
-        let killed_enemies = 0;
-        let spawn_rate = 0.02;
-        let triangle_image = Image::new(ctx, "/Bomba.png")?;
-        let hexagon_image = Image::new(ctx, "/2ndenemy.png")?;
-        let boss_image = Image::new(ctx, "/BOSS.png")?;
-        let tlo = Image::new(ctx, "/tlo2.png")?;
-        let game_state = GameState::Menu;
+    // Plays `replay` back if given, otherwise records the player's input
+    pub fn new(ctx: &mut Context, options: &Options, replay: Option<Replay>) -> GameResult<Game> {
+        let (seed, endless) = match &replay {
+            Some(replay) => (replay.seed, replay.endless),
+            None => (options.seed, options.endless),
+        };
+        let world = World::load(seed, endless)?;
+        let sprites = Sprites::new(ctx, &world.catalogue)?;
+        let renderer = Renderer::new(&sprites);
+        let (window_w, window_h) = graphics::drawable_size(ctx);
+        let camera = Camera::new(window_w, window_h);
+        camera.apply(ctx)?;
+        let input = InputState::default();
+        let mouse_pos = mouse::position(ctx);
+        let aim = Aim::At(camera.screen_to_world(na::Point2::new(mouse_pos.x, mouse_pos.y)));
+        let screens = vec![if replay.is_some() { Screen::Playing } else { Screen::Menu }];
         let menu = MenuParallax::new(ctx)?;
-        Ok(Game { player, shop, enemies, bullets, is_boss, level, killed_enemies, spawn_rate, triangle_image, hexagon_image, boss_image, _player_image, tlo, game_state, menu})
-    }
-
-    fn spawn_enemy(&mut self) {
-        let mut rng = rand::thread_rng();
-        let side = rng.gen_range(0..4); // Choose one of the 4 sides: 0=top, 1=right, 2=bottom, 3=left
-    
-        let x_pos = match side {
-            0 => rng.gen_range(0.0..1500.0),   // Top edge (random x, y=0)
-            1 => 1500.0,                       // Right edge (x=1500, random y)
-            2 => rng.gen_range(0.0..1500.0),   // Bottom edge (random x, y=1000)
-            3 => 0.0,                          // Left edge (x=0, random y)
-            _ => unreachable!(),
+        // A file that doesn't parse is left alone so the player can fix it
+        let (bindings, bindings_error) = match Bindings::load() {
+            Ok(bindings) => (bindings, None),
+            Err(e) => {
+                println!("Could not load controls, using the defaults and not saving over {}: {}", BINDINGS_FILE, e);
+                (Bindings::default(), Some(e.to_string()))
+            }
         };
+        let recording = Replay::new(seed, endless);
+        let record_path = options.record.clone().unwrap_or_else(|| PathBuf::from("last_run.replay"));
+        let playback = replay.map(|replay| replay.frames.into_iter());
+        Ok(Game { world, input, aim, sprites, renderer, camera, screens, selected: 0, held: InputState::default(), bindings,
+            bindings_error, rebinding: None, confirming: None, weapon_switch: None, menu,
+            recording, record_path, playback, show_hitboxes: false, show_stats: false, fullscreen: false, tick_allocations: 0 })
+    }
 
-        let y_pos = match side {
-            0 => 0.0,                          // Top edge (y=0, random x)
-            1 => rng.gen_range(0.0..1000.0),   // Right edge (random y, x=1500)
-            2 => 1000.0,                       // Bottom edge (y=1000, random x)
-            3 => rng.gen_range(0.0..1000.0),   // Left edge (random y, x=0)
-            _ => unreachable!(),
-        };
+    fn screen(&self) -> Screen {
+        *self.screens.last().expect("screen stack is never empty")
+    }
 
-        let enemy_type = rng.gen_range(0..3); // Randomly choose between Triangle and Hexagon
-    
-        println!("Spawning enemy at ({}, {})", x_pos, y_pos);
-        
-        match enemy_type {
-            0 => { // Spawn TriangleEnemy
-                let enemy = TriangleEnemy::new(na::Point2::new(x_pos, y_pos), self.level, self.triangle_image.clone());
-                self.enemies.push(Box::new(enemy));
-            }
-            1 => { // Spawn HexagonEnemy
-                let enemy = HexagonEnemy::new(na::Point2::new(x_pos, y_pos), self.level, self.hexagon_image.clone());
-                self.enemies.push(Box::new(enemy));
+    // Options of the active screen, the shop lists its upgrades first
+    fn options(&self) -> Vec<&str> {
+        match self.screen() {
+            Screen::Shop => self.world.shop.upgrades.iter().map(|def| def.name.as_str()).chain(Screen::Shop.options()).collect(),
+            screen => screen.options(),
+        }
+    }
+
+    // Screen changes remember which buttons are down so they don't leak into the next screen
+    fn push_screen(&mut self, ctx: &Context, screen: Screen) {
+        self.screens.push(screen);
+        self.selected = 0;
+        self.confirming = None;
+        self.held = InputState::capture(ctx, &self.bindings, self.aim);
+    }
+
+    fn pop_screen(&mut self, ctx: &Context) {
+        if self.screens.len() > 1 {
+            self.screens.pop();
+        }
+        self.selected = 0;
+        self.confirming = None;
+        self.held = InputState::capture(ctx, &self.bindings, self.aim);
+    }
+
+    fn set_screen(&mut self, ctx: &Context, screen: Screen) {
+        self.screens.clear();
+        self.push_screen(ctx, screen);
+    }
+
+    // Throws the current run away and prepares a new one with a fresh seed
+    fn reset(&mut self) -> GameResult {
+        let seed = rand::random();
+        let endless = self.world.waves.is_none();
+        self.world = World::load(seed, endless)?;
+        self.recording = Replay::new(seed, endless);
+        self.playback = None;
+        self.input = InputState::default();
+        self.weapon_switch = None;
+        Ok(())
+    }
+
+    // Leaves the current run, saving its replay
+    fn end_run(&mut self) -> GameResult {
+        if self.world.frame > 0 {
+            self.save_recording();
+        }
+        self.reset()
+    }
+
+    fn step_world(&mut self, frame: ReplayFrame) {
+        self.recording.record(frame.dt, &frame.input);
+        let allocations_before = allocations();
+        self.world.update(frame.dt, &frame.input);
+        self.tick_allocations = allocations() - allocations_before;
+    }
+
+    fn update_playing(&mut self, ctx: &mut Context) {
+        match self.next_frame(ctx) {
+            Some(frame) => {
+                self.input = frame.input;
+                self.step_world(frame);
             }
-            2 => { // Spawn Boss
-                if !self.is_boss {
-                    let boss_chance = rng.gen_range(0..10); // 10% chance to spawn boss
-                    if boss_chance == 0 {
-                        let enemy = Boss::new(na::Point2::new(x_pos, y_pos), self.level, self.boss_image.clone());
-                        self.enemies.push(Box::new(enemy));
-                        self.is_boss = true;
-                    }
-                }
+            None => {
+                println!("Replay finished");
+                self.set_screen(ctx, Screen::GameOver);
             }
-            _ => {}
         }
 
-        //println!("Enemies count: {}", self.enemies.len());
-        //println!("Bullets count: {}", self.bullets.len());
+        if self.world.is_over() {
+            println!("Game Over!");
+            println!("Score: {}", self.world.player.points);
+            println!("Seed: {}", self.world.seed);
+            self.save_recording();
+            self.set_screen(ctx, Screen::GameOver);
+        }
     }
 
-    fn handle_shop_buy(&mut self, ctx: &mut Context) {
-        // Buy upgrades by pressing keys 1, 2, 3, 4
-        if ggez::input::keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Key1) {
-            self.shop.try_buy_health_upgrade(&mut self.player);
+    // Enter on the highlighted option of a list screen
+    fn choose(&mut self, ctx: &mut Context) -> GameResult {
+        match (self.screen(), self.selected) {
+            (Screen::Menu, 0) => self.set_screen(ctx, Screen::Playing),
+            (Screen::Menu, 1) => self.push_screen(ctx, Screen::Settings),
+            (Screen::Menu, _) => ggez::event::quit(ctx),
+
+            (Screen::Paused, 0) => self.pop_screen(ctx),
+            (Screen::Paused, 1) => self.push_screen(ctx, Screen::Shop),
+            (Screen::Paused, 2) => self.push_screen(ctx, Screen::Settings),
+            (Screen::Paused, 3) => {
+                self.end_run()?;
+                self.set_screen(ctx, Screen::Playing);
+            }
+            (Screen::Paused, _) => {
+                self.end_run()?;
+                self.set_screen(ctx, Screen::Menu);
+            }
+
+            (Screen::Settings, 0) => self.show_hitboxes = !self.show_hitboxes,
+            (Screen::Settings, 1) => self.show_stats = !self.show_stats,
+            (Screen::Settings, 2) => {
+                self.fullscreen = !self.fullscreen;
+                let mode = if self.fullscreen { FullscreenType::Desktop } else { FullscreenType::Windowed };
+                graphics::set_fullscreen(ctx, mode)?;
+            }
+            (Screen::Settings, 3) => {
+                let deadzones = &mut self.bindings.deadzones;
+                deadzones.movement = Deadzones::next_step(deadzones.movement);
+                self.save_bindings();
+            }
+            (Screen::Settings, 4) => {
+                let deadzones = &mut self.bindings.deadzones;
+                deadzones.aim = Deadzones::next_step(deadzones.aim);
+                self.save_bindings();
+            }
+            (Screen::Settings, 5) => self.push_screen(ctx, Screen::Controls),
+            (Screen::Settings, _) => self.pop_screen(ctx),
+
+            (Screen::Shop, i) if i < self.world.shop.upgrades.len() => self.ask_to_buy(i),
+            (Screen::Shop, i) if i == self.world.shop.upgrades.len() => self.undo_purchase(),
+            (Screen::Shop, _) => self.pop_screen(ctx),
+
+            (Screen::Controls, i) if i < Action::ALL.len() => self.rebinding = Some(Action::ALL[i]),
+            (Screen::Controls, i) if i == Action::ALL.len() => {
+                self.bindings = Bindings::default();
+                self.save_bindings();
+            }
+            (Screen::Controls, _) => self.pop_screen(ctx),
+
+            (Screen::GameOver, 0) => {
+                self.reset()?;
+                self.set_screen(ctx, Screen::Playing);
+            }
+            (Screen::GameOver, _) => {
+                self.reset()?;
+                self.set_screen(ctx, Screen::Menu);
+            }
+
+            (Screen::Playing, _) => {}
         }
-        if ggez::input::keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Key2) {
-            self.shop.try_buy_damage_upgrade(&mut self.player);
+        Ok(())
+    }
+
+    fn save_bindings(&self) {
+        if self.bindings_error.is_some() {
+            println!("Not saving {}, it could not be loaded", BINDINGS_FILE);
+            return;
         }
-        if ggez::input::keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Key3) {
-            self.shop.try_buy_speed_upgrade(&mut self.player);
+        if let Err(e) = self.bindings.save() {
+            println!("Could not save {}: {}", BINDINGS_FILE, e);
         }
-        if ggez::input::keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Key4) {
-            self.shop.try_buy_fire_rate_upgrade(&mut self.player);
+    }
+
+    // The key or button pressed while the controls screen waits for one becomes a new binding
+    fn rebind(&mut self, action: Action, pressed: Binding) {
+        self.rebinding = None;
+        let cancel = [Binding::Key(KeyCode::Escape), Binding::Pad(Button::Start)];
+        if cancel.contains(&pressed) || !pressed.is_supported() {
+            return;
         }
+        self.bindings.bind(action, pressed);
+        self.save_bindings();
     }
 
-    fn handle_enemy_bullet_logic(&mut self, ctx: &mut Context) -> (Vec<usize>, Vec<usize>) {
-        // Creates a vector of indexes to remove (enemy and bullets)
-        let mut enemies_to_remove = Vec::new();
-        let mut player_bullets_to_remove = Vec::new();
+    // One-shot actions: screen changes, menus and debug toggles
+    fn handle_press(&mut self, ctx: &mut Context, pressed: Binding) -> GameResult {
+        if let Some(action) = self.rebinding {
+            self.rebind(action, pressed);
+            return Ok(());
+        }
 
-        // Update all enemies
-        for (i, enemy) in self.enemies.iter_mut().enumerate() {
-            enemy.update(&self.player, ctx, &mut self.bullets); // Udpating
+        let bindings = &self.bindings;
+        let is = |action| bindings.triggers(action, pressed);
+        let (back, pause, shop) = (is(Action::Back), is(Action::Pause), is(Action::Shop));
+        let (up, down, confirm) = (is(Action::MenuUp), is(Action::MenuDown), is(Action::Confirm));
+        let buy = [is(Action::Buy1), is(Action::Buy2), is(Action::Buy3), is(Action::Buy4)].iter().position(|&pressed| pressed);
+        let slot = [is(Action::Weapon1), is(Action::Weapon2), is(Action::Weapon3), is(Action::Weapon4), is(Action::Weapon5)]
+            .iter().position(|&pressed| pressed);
+        let switch = slot.map(WeaponSwitch::Slot)
+            .or_else(|| is(Action::NextWeapon).then_some(WeaponSwitch::Next))
+            .or_else(|| is(Action::PreviousWeapon).then_some(WeaponSwitch::Previous));
+
+        if is(Action::Hitboxes) {
+            self.show_hitboxes = !self.show_hitboxes;
+        }
+        if is(Action::Stats) {
+            self.show_stats = !self.show_stats;
+        }
 
-            if enemy.check_collision(&self.player) {
-                enemy.apply_damage(&mut self.player); // Apply damage to player
-                //println!("Enemy touched player! Player HP: {}", self.player.hp);
+        // Screen changes and purchases, anything else may move through a list below
+        let handled = match self.screen() {
+            Screen::Playing if pause => {
+                self.push_screen(ctx, Screen::Paused);
+                true
             }
-
-            // Check for collisions between player bullets and enemies
-            for (j, bullet) in self.player.bullets.iter_mut().enumerate() {
-                if bullet.check_collision_with_enemy(&**enemy) {
-                    if bullet.apply_damage_to_enemy(&mut **enemy) == 0 { 
-                        if !enemies_to_remove.contains(&i) { // Check if the enemy is already marked for removal
-                            if enemy.is_boss() { // Controls boss state (1 boss at a time)
-                                self.is_boss = false;
-                            }
-
-                            enemies_to_remove.push(i);
-
-                            self.killed_enemies += 1;
-                            self.player.points += enemy.get_points();
-                            self.player.coins += enemy.get_coins();
-                        }
+            Screen::Playing if shop && self.world.between_waves() => {
+                self.push_screen(ctx, Screen::Shop);
+                true
+            }
+            Screen::Playing if switch.is_some() => {
+                self.weapon_switch = switch;
+                true
+            }
+            Screen::Shop if self.confirming.is_some() => {
+                if confirm {
+                    if let Some(upgrade) = self.confirming.take() {
+                        self.buy_from_shop(upgrade);
                     }
-                    player_bullets_to_remove.push(j);
-                    //println!("Bullet touched enemy! Enemy HP: {}", enemy.get_hp());
+                } else if back {
+                    self.confirming = None;
+                }
+                true
+            }
+            Screen::Shop if back || shop => {
+                self.pop_screen(ctx);
+                true
+            }
+            Screen::Shop if buy.is_some() => {
+                // The number keys jump straight to the question for their upgrade
+                if let Some(upgrade) = buy {
+                    self.ask_to_buy(upgrade);
                 }
+                true
+            }
+            Screen::Paused | Screen::Settings | Screen::Controls if back || (pause && self.screen() == Screen::Paused) => {
+                self.pop_screen(ctx);
+                true
+            }
+            Screen::Menu if back => {
+                ggez::event::quit(ctx);
+                true
+            }
+            Screen::GameOver if back => {
+                self.reset()?;
+                self.set_screen(ctx, Screen::Menu);
+                true
+            }
+            _ => false,
+        };
+        if handled {
+            return Ok(());
+        }
 
-                if bullet.is_off_screen() {
-                    if player_bullets_to_remove.contains(&j) {
-                        continue;
-                    }
-                    player_bullets_to_remove.push(j);
+        // Moving through the options of a list screen
+        let options = self.options().len();
+        if options > 0 {
+            if up {
+                self.selected = (self.selected + options - 1) % options;
+            } else if down {
+                self.selected = (self.selected + 1) % options;
+            } else if confirm {
+                self.choose(ctx)?;
+            } else if self.screen() == Screen::Controls && [Binding::Key(KeyCode::Back), Binding::Pad(Button::North)].contains(&pressed) {
+                // Backspace or Y clears the highlighted action
+                if let Some(&action) = Action::ALL.get(self.selected) {
+                    self.bindings.clear(action);
+                    self.save_bindings();
                 }
             }
         }
+        Ok(())
+    }
+
+    // Purchases need a second confirm, upgrades the player can't pay for don't ask
+    fn ask_to_buy(&mut self, upgrade: usize) {
+        if upgrade >= self.world.shop.upgrades.len() {
+            return;
+        }
+        self.selected = upgrade;
+        if self.playback.is_none() && self.world.shop.blocked(upgrade, &self.world.player).is_none() {
+            self.confirming = Some(upgrade);
+        }
+    }
 
-        (enemies_to_remove, player_bullets_to_remove)
+    // Purchases made on the shop screen are zero length steps so replays repeat them
+    fn buy_from_shop(&mut self, upgrade: usize) {
+        if self.playback.is_some() {
+            return;
+        }
+        let input = InputState { buy: Some(upgrade), ..InputState::default() };
+        self.step_world(ReplayFrame { dt: 0.0, input });
     }
 
-    fn handle_player_bullet_logic(&mut self) -> Vec<usize> {
-        // Creates a vector of indexes to remove
-        let mut bullets_to_remove = Vec::new();
+    fn undo_purchase(&mut self) {
+        if self.playback.is_some() {
+            return;
+        }
+        let input = InputState { undo: true, ..InputState::default() };
+        self.step_world(ReplayFrame { dt: 0.0, input });
+    }
 
-        // Update all bullets and check if bullets hit the player
-        for (i, bullet) in self.bullets.iter_mut().enumerate() {
-            bullet.update();
-            if bullet.check_collision_with_player(&self.player) == true {
-                bullet.apply_damage(&mut self.player);
-                bullets_to_remove.push(i);
-            }
+    fn save_recording(&self) {
+        if self.playback.is_some() {
+            return;
+        }
+        match self.recording.save(&self.record_path) {
+            Ok(()) => println!("Replay saved to {}", self.record_path.display()),
+            Err(e) => println!("Could not save replay: {}", e),
+        }
+    }
 
-            if bullet.is_off_screen() {
-                if bullets_to_remove.contains(&i) {
-                    continue;
-                }
-                bullets_to_remove.push(i);
+    // Next frame's input, from the replay when playing one back
+    fn next_frame(&mut self, ctx: &mut Context) -> Option<ReplayFrame> {
+        match &mut self.playback {
+            Some(frames) => frames.next(),
+            None => {
+                let mut input = InputState::capture(ctx, &self.bindings, self.aim);
+                input.mask_held(&mut self.held);
+                input.weapon = self.weapon_switch.take();
+                self.aim = input.aim;
+                Some(ReplayFrame { dt: FIXED_DT, input })
             }
         }
-
-        bullets_to_remove   
     }
 
+    // Hides what was drawn outside the playfield and shows the frame
+    fn present(&self, ctx: &mut Context) -> GameResult {
+        self.camera.draw_letterbox(ctx)?;
+        graphics::present(ctx)
+    }
 
     pub fn draw_death_screen(&mut self, ctx: &mut Context) -> GameResult {
         graphics::clear(ctx, graphics::Color::from_rgb(0, 0, 0));
-        let text = format!("SCORE:{}", self.player.points);
+        let text = format!("SCORE:{}", self.world.player.points);
+        let seed_text = format!("SEED: {}", self.world.seed);
 
         let display_text = graphics::Text::new((text, graphics::Font::default(), 40.0));
+        let display_seed = graphics::Text::new((seed_text, graphics::Font::default(), 25.0));
 
         // Use the tuple directly in DrawParam::dest()
-        graphics::draw(ctx, &display_text, DrawParam::default().dest([650.0, 450.0]))?;
-        println!("Game Over!");
-        println!("Score: {}", self.player.points);
-        graphics::present(ctx)?;
+        graphics::draw(ctx, &display_text, DrawParam::default().dest([650.0, 100.0]))?;
+        graphics::draw(ctx, &display_seed, DrawParam::default().dest([650.0, 160.0]))?;
+        screen::draw_options(ctx, "GAME OVER", &Screen::GameOver.options(), &[], self.selected)
+    }
+
+    // alpha is how far rendering is between the previous and the next update
+    pub fn draw_playing_screen(&mut self, ctx: &mut Context, alpha: f32) -> GameResult {
+        graphics::clear(ctx, graphics::Color::from_rgb(166, 153, 153));
+        graphics::draw(ctx, &self.sprites.background, DrawParam::default())?;
+        for enemy in self.world.enemies.values() {
+            enemy.draw(&self.world.catalogue, &mut self.renderer, alpha)?;
+        }
+
+        for bullet in self.world.bullets.values() {
+            bullet.draw(&mut self.renderer, alpha)?
+        }
+        for pickup in &self.world.pickups {
+            pickup.draw(&mut self.renderer)?;
+        }
+        for shockwave in &self.world.shockwaves {
+            shockwave.draw(&mut self.renderer, alpha)?;
+        }
 
+        self.world.player.draw(ctx, &mut self.renderer, &self.input.aim, alpha)?;
+        self.renderer.flush(ctx)?;
+        self.draw_wave_info(ctx)?;
+
+        if self.show_hitboxes {
+            self.draw_hitboxes(ctx)?;
+        }
+        if self.show_stats {
+            self.draw_stats(ctx)?;
+        }
         Ok(())
     }
 
-    pub fn draw_playing_screen(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, graphics::Color::from_rgb(166, 153, 153));
-        graphics::draw(ctx, &self.tlo, DrawParam::default())?;
-        for enemy in &self.enemies {
-            enemy.draw(ctx)?;
+    // Level, next effect and cost of every upgrade, and the question when buying one
+    fn draw_shop_screen(&mut self, ctx: &mut Context) -> GameResult {
+        let (shop, player) = (&self.world.shop, &self.world.player);
+        let mut values: Vec<Option<String>> = (0..shop.upgrades.len()).map(|upgrade| Some(shop.describe(upgrade, player))).collect();
+        values.push(Some(shop.last_purchase().map_or("-", |def| def.name.as_str()).to_string()));
+        screen::draw_options(ctx, "SHOP", &self.options(), &values, self.selected)?;
+
+        let coins = graphics::Text::new((format!("COINS: {}", player.coins), graphics::Font::default(), 40.0));
+        let coins_w = coins.width(ctx);
+        graphics::draw(ctx, &coins, DrawParam::default().dest([(crate::playfield::WIDTH - coins_w) / 2.0, 860.0]))?;
+
+        let hint = match self.confirming {
+            Some(upgrade) => format!("BUY {} FOR {} COINS?  ENTER: YES  ESC: NO", shop.upgrades[upgrade].name, shop.cost(upgrade)),
+            None => "ENTER OR 1-4: BUY    ESC: CLOSE".to_string(),
+        };
+        let hint = graphics::Text::new((hint, graphics::Font::default(), 30.0));
+        let hint_w = hint.width(ctx);
+        let color = if self.confirming.is_some() { graphics::Color::from_rgb(255, 220, 0) } else { graphics::Color::WHITE };
+        graphics::draw(ctx, &hint, DrawParam::default().dest([(crate::playfield::WIDTH - hint_w) / 2.0, 950.0]).color(color))
+    }
+
+    // ON / OFF next to the settings that are toggles, deadzones in percent
+    fn draw_settings_screen(&mut self, ctx: &mut Context) -> GameResult {
+        let on_off = |on: bool| Some(if on { "ON" } else { "OFF" }.to_string());
+        let percent = |value: f32| Some(format!("{:.0}%", value * 100.0));
+        let deadzones = self.bindings.deadzones;
+        let values = [on_off(self.show_hitboxes), on_off(self.show_stats), on_off(self.fullscreen),
+            percent(deadzones.movement), percent(deadzones.aim), None, None];
+        screen::draw_options(ctx, "SETTINGS", &Screen::Settings.options(), &values, self.selected)?;
+        self.draw_bindings_error(ctx)
+    }
+
+    fn draw_controls_screen(&mut self, ctx: &mut Context) -> GameResult {
+        let values: Vec<Option<String>> = Action::ALL.iter().map(|&action| Some(self.bindings.describe(action))).collect();
+        screen::draw_options(ctx, "CONTROLS", &Screen::Controls.options(), &values, self.selected)?;
+
+        let hint = match self.rebinding {
+            Some(action) => format!("PRESS A KEY OR BUTTON FOR {} (ESC / START CANCELS)", action.label()),
+            None => "ENTER / A: ADD A KEY    BACKSPACE / Y: CLEAR".to_string(),
+        };
+        let hint = graphics::Text::new((hint, graphics::Font::default(), 30.0));
+        let hint_w = hint.width(ctx);
+        graphics::draw(ctx, &hint, DrawParam::default().dest([(crate::playfield::WIDTH - hint_w) / 2.0, 950.0]))?;
+        self.draw_bindings_error(ctx)
+    }
+
+    // Warns above the title that changes to the controls won't be saved
+    fn draw_bindings_error(&self, ctx: &mut Context) -> GameResult {
+        let error = match &self.bindings_error {
+            Some(error) => error,
+            None => return Ok(()),
+        };
+        let warning = format!("COULD NOT LOAD {}, CHANGES ARE NOT SAVED\n{}", BINDINGS_FILE.to_uppercase(), error);
+        let mut text = graphics::Text::new((warning, graphics::Font::default(), 24.0));
+        text.set_bounds([crate::playfield::WIDTH - 100.0, f32::INFINITY], graphics::Align::Center);
+        graphics::draw(ctx, &text, DrawParam::default().dest([50.0, 150.0]).color(graphics::Color::from_rgb(255, 80, 80)))
+    }
+
+    // Debug view of every collider, toggled with F1
+    fn draw_hitboxes(&self, ctx: &mut Context) -> GameResult {
+        let world = &self.world;
+        for enemy in world.enemies.values() {
+            enemy.collider().draw_outline(ctx, enemy.get_pos(), graphics::Color::from_rgb(255, 0, 255))?;
         }
-    
-        for bullet in &self.bullets {
-            bullet.draw(ctx)?
+        for bullet in world.bullets.values() {
+            bullet.collider().draw_outline(ctx, &bullet.pos, graphics::Color::from_rgb(255, 255, 0))?;
         }
+        world.player.collider.draw_outline(ctx, &world.player.player_pos, graphics::Color::from_rgb(0, 255, 0))
+    }
 
-        self.shop.display(ctx, &mut self.player)?;
-        self.player.draw(ctx)?; 
+    // Debug overlay with pool usage and allocation counts, toggled with F2
+    fn draw_stats(&self, ctx: &mut Context) -> GameResult {
+        let pool_line = |name: &str, pool: &BulletPool| {
+            format!("{}: {}/{} (peak {}, dropped {})",
+                name, pool.len(), pool.capacity().unwrap_or(0), pool.peak(), pool.dropped())
+        };
+        let lines = [
+            format!("FPS: {:.0}", ggez::timer::fps(ctx)),
+            format!("Enemies: {}", self.world.enemies.len()),
+            pool_line("Bullets", &self.world.bullets),
+            format!("Draw calls for entities: {}", self.renderer.draw_calls),
+            format!("Allocations last tick: {}, total: {}", self.tick_allocations, allocations()),
+        ];
+
+        let text = graphics::Text::new((lines.join("\n"), graphics::Font::default(), 20.0));
+        graphics::draw(ctx, &text, DrawParam::default().dest([1000.0, 50.0]).color(graphics::Color::from_rgb(255, 255, 0)))
+    }
 
+    fn draw_wave_info(&mut self, ctx: &mut Context) -> GameResult {
+        let waves = match &self.world.waves {
+            Some(waves) => waves,
+            None => return Ok(()),
+        };
+
+        let wave_text = graphics::Text::new((waves.wave_name(), graphics::Font::default(), 30.0));
+        graphics::draw(ctx, &wave_text, DrawParam::default().dest([1200.0, 10.0]))?;
+
+        if let WavePhase::Cleared { .. } = waves.phase {
+            let cleared_text = graphics::Text::new(("WAVE CLEARED", graphics::Font::default(), 80.0));
+            graphics::draw(ctx, &cleared_text, DrawParam::default().dest([500.0, 400.0]))?;
+
+            let shop_hint = format!("{}: SHOP", self.bindings.describe(Action::Shop).to_uppercase());
+            let shop_hint = graphics::Text::new((shop_hint, graphics::Font::default(), 30.0));
+            graphics::draw(ctx, &shop_hint, DrawParam::default().dest([500.0, 500.0]))?;
+        }
         Ok(())
     }
 
     pub fn draw_menu_screen(&mut self, ctx: &mut Context) -> GameResult {
         self.menu.draw(ctx)?;
-        let text = format!("OTOCZONY");
-        let text2 = format!("PRESS SPACE TO START");
+        let text = "OTOCZONY";
 
         let display_text = graphics::Text::new((text, graphics::Font::default(), 100.0));
-        let display_text2 = graphics::Text::new((text2, graphics::Font::default(), 50.0));
 
-        graphics::draw(ctx, &display_text, DrawParam::default().dest([525.0, 350.0]))?;
-        graphics::draw(ctx, &display_text2, DrawParam::default().dest([475.0, 550.0]))?;
-        graphics::present(ctx)?;
+        graphics::draw(ctx, &display_text, DrawParam::default().dest([525.0, 200.0]))?;
+        screen::draw_options(ctx, "", &Screen::Menu.options(), &[], self.selected)
+    }
 
-        Ok(())
+    fn draw_screen(&mut self, ctx: &mut Context, screen: Screen, alpha: f32) -> GameResult {
+        match screen {
+            Screen::Menu => self.draw_menu_screen(ctx),
+            Screen::Playing => self.draw_playing_screen(ctx, alpha),
+            Screen::GameOver => self.draw_death_screen(ctx),
+            Screen::Paused => screen::draw_options(ctx, "PAUSED", &Screen::Paused.options(), &[], self.selected),
+            Screen::Shop => self.draw_shop_screen(ctx),
+            Screen::Settings => self.draw_settings_screen(ctx),
+            Screen::Controls => self.draw_controls_screen(ctx),
+        }
     }
 }
 
 impl EventHandler for Game {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        if self.game_state == GameState::Menu {
-            self.menu.update();
-            if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Space) {
-                self.game_state = GameState::Playing;
+        // Run as many fixed steps as the elapsed real time allows
+        while ggez::timer::check_update_time(ctx, TICK_RATE) {
+            match self.screen() {
+                Screen::Menu => self.menu.update(),
+                Screen::Playing => self.update_playing(ctx),
+                // Everything else freezes the run
+                Screen::Paused | Screen::Shop | Screen::Settings | Screen::Controls | Screen::GameOver => {}
             }
         }
-        else if self.game_state == GameState::Playing {
-            self.handle_shop_buy(ctx);
-
-            self.player.update(ctx)?;
-
-            let (enemies_to_remove, player_bullets_to_remove) = self.handle_enemy_bullet_logic(ctx);
-            
-            let bullets_to_remove = self.handle_player_bullet_logic();
-
-            //println!{"Player bullets {}, enemies {}, bullets {}", self.player.bullets.len(), self.enemies.len(), self.bullets.len()};
+        Ok(())
+    }
 
-            for &index in player_bullets_to_remove.iter().rev() {
-                if index < self.player.bullets.len() {
-                    self.player.bullets.remove(index);
-                    //println!("Bullet removed from player's bullets at index {}", index);
-                }
-            }
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.camera.resize(width, height);
+        if let Err(e) = self.camera.apply(ctx) {
+            println!("Could not resize the view: {}", e);
+        }
+    }
 
-            // Remove enemies and bullets
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, repeat: bool) {
+        if repeat {
+            return;
+        }
+        if let Err(e) = self.handle_press(ctx, Binding::Key(keycode)) {
+            println!("Error: {}", e);
+        }
+    }
 
-            for i in bullets_to_remove.iter().rev() {
-                self.bullets.remove(*i);
-                //println!("Bullet removed {}", i);
-            }
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
+        if let Err(e) = self.handle_press(ctx, Binding::Mouse(button)) {
+            println!("Error: {}", e);
+        }
+    }
 
-            for i in enemies_to_remove.iter().rev() {
-                self.enemies.remove(*i);
-                //println!("Enemy removed {}", i);
-            }
+    // Scrolling down picks the next weapon, up the previous one
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        if self.screen() == Screen::Playing && y != 0.0 {
+            self.weapon_switch = Some(if y < 0.0 { WeaponSwitch::Next } else { WeaponSwitch::Previous });
+        }
+    }
 
-            // Spawn enemies randomly
-            if rand::random::<f32>() < self.spawn_rate {
-                self.spawn_enemy();
-            }
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.aim = Aim::At(self.camera.screen_to_world(na::Point2::new(x, y)));
+    }
 
-            // Check if level should be increased
-            if self.killed_enemies >= 30 {
-                self.level += 1;
-                self.killed_enemies = 0;
-                self.spawn_rate += 0.01;
-            }
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, _id: GamepadId) {
+        if let Err(e) = self.handle_press(ctx, Binding::Pad(button)) {
+            println!("Error: {}", e);
+        }
+    }
 
-            if self.player.is_dead() == true {
-                self.game_state = GameState::GameOver;
-            }
+    // Closing the window in the middle of a run still saves its replay
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        let in_run = self.screens.contains(&Screen::Playing);
+        if in_run && self.world.frame > 0 {
+            self.save_recording();
         }
-        //println!("Level: {}, Enemies killed: {}, Player hp: {}, damage: {}, speed: {}, coins: {}, SpawnRate {}", self.level, self.killed_enemies, self.player.hp, self.player.damage, self.player.speed, self.player.coins, self.spawn_rate);
-        //println!("Player hp: {}, damage: {}, speed: {}, coins: {}", self.player.hp, self.player.damage, self.player.speed, self.player.coins);
-        Ok(())
+        false
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        // The bottom screen is drawn in full, an overlay on top of it gets a dimmed background
+        let base = self.screens[0];
+        let top = self.screen();
+        let alpha = if top == Screen::Playing {
+            // How far we are between the last update and the next one
+            (ggez::timer::remaining_update_time(ctx).as_secs_f32() / FIXED_DT).min(1.0)
+        } else {
+            1.0
+        };
 
-        // Draw End screen if player dies else draw content
-        if self.game_state == GameState::GameOver {
-            self.draw_death_screen(ctx)?;
+        self.draw_screen(ctx, base, alpha)?;
+        if top != base {
+            screen::draw_dim(ctx)?;
+            self.draw_screen(ctx, top, alpha)?;
         }
-        else if self.game_state == GameState::Playing {
-            self.draw_playing_screen(ctx)?;
-        }
-        else if self.game_state == GameState::Menu {
-            self.draw_menu_screen(ctx)?;
-        }
-        // Present the drawn content
-        ggez::timer::sleep(std::time::Duration::from_secs_f32(1.0 / 60.0)); // 60 FPS
-        //otoczony
-
-        Ok(())
+        self.present(ctx)
     }
 }
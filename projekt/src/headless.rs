@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use nalgebra as na;
+use rand::{Rng, SeedableRng};
+
+use crate::input::{Aim, InputState};
+use crate::replay::Replay;
+use crate::bullet::{Bullet, BulletPool, Team, BULLET_CAPACITY};
+use crate::catalogue::Behaviour;
+use crate::pattern::{expire_bullets, Emitter, PatternLibrary, ShotStats};
+use crate::world::{GameRng, World, FIXED_DT};
+use crate::alloc_counter::allocations;
+use crate::playfield;
+
+use ggez::{GameError, GameResult};
+
+// Simple autopilot: stands still and shoots at the closest enemy
+fn bot_input(world: &World) -> InputState {
+    let player_pos = world.player.player_pos;
+    let target = world.enemies.values()
+        .map(|enemy| *enemy.get_pos())
+        .min_by(|a, b| na::distance(a, &player_pos).total_cmp(&na::distance(b, &player_pos)));
+
+    match target {
+        Some(target) => InputState { fire: true, aim: Aim::At(target), ..InputState::default() },
+        None => InputState::default(),
+    }
+}
+
+fn print_summary(world: &World) {
+    println!("Seed: {}, Frames: {}, Time: {:.2}s", world.seed, world.frame, world.time);
+    if let Some(waves) = &world.waves {
+        println!("Reached: {}", waves.wave_name());
+    }
+    println!("Level: {}, Points: {}, Coins: {}, HP: {}", world.level, world.player.points, world.player.coins, world.player.hp);
+    println!("Enemies: {}, Enemy bullets: {}, Player bullets: {}", world.enemies.len(), world.bullet_count(Team::Enemy), world.bullet_count(Team::Player));
+}
+
+// Runs the simulation without a window for the given number of frames
+pub fn simulate(frames: u64, seed: u64, endless: bool, record: Option<&Path>) -> GameResult<World> {
+    let mut world = World::load(seed, endless)?;
+    let mut recording = Replay::new(seed, endless);
+    for _ in 0..frames {
+        let input = bot_input(&world);
+        recording.record(FIXED_DT, &input);
+        world.update(FIXED_DT, &input);
+        if world.is_over() {
+            break;
+        }
+    }
+
+    if let Some(path) = record {
+        match recording.save(path) {
+            Ok(()) => println!("Replay saved to {}", path.display()),
+            Err(e) => println!("Could not save replay: {}", e),
+        }
+    }
+
+    print_summary(&world);
+    Ok(world)
+}
+
+// Plays a recorded run back without a window
+pub fn replay(replay: &Replay) -> GameResult<World> {
+    let mut world = World::load(replay.seed, replay.endless)?;
+    for frame in &replay.frames {
+        world.update(frame.dt, &frame.input);
+        if world.is_over() {
+            break;
+        }
+    }
+
+    print_summary(&world);
+    Ok(world)
+}
+
+const BENCH_TICKS: usize = 600;
+const BENCH_ENEMIES: usize = 500;
+const BENCH_ENEMY_BULLETS: usize = 4000;
+const BENCH_PLAYER_BULLETS: usize = 1000;
+const BENCH_WARMUP_TICKS: usize = 60; // Buffers may still grow during these
+
+fn random_point(world: &mut World) -> na::Point2<f32> {
+    na::Point2::new(world.rng.gen_range(0.0..playfield::WIDTH), world.rng.gen_range(0.0..playfield::HEIGHT))
+}
+
+// Keeps the entity counts at the benchmark's target
+fn top_up(world: &mut World) {
+    let archetypes: Vec<usize> = (0..world.catalogue.enemies.len())
+        .filter(|&i| world.catalogue.enemies[i].behaviour != Behaviour::Boss)
+        .collect();
+    while world.enemies.len() < BENCH_ENEMIES {
+        let archetype = archetypes[world.rng.gen_range(0..archetypes.len())];
+        let pos = random_point(world);
+        world.spawn_archetype(archetype, pos);
+    }
+    for (team, wanted, damage) in [(Team::Enemy, BENCH_ENEMY_BULLETS, 0), (Team::Player, BENCH_PLAYER_BULLETS, 1)] {
+        for _ in world.bullet_count(team)..wanted {
+            let (pos, target) = (random_point(world), random_point(world));
+            world.bullets.insert(Bullet::new(team, pos, target, 200.0, damage, 10.0));
+        }
+    }
+}
+
+// Measures how long one tick takes with 5000 bullets and 500 enemies alive
+pub fn benchmark(seed: u64) -> GameResult<()> {
+    let mut world = World::load(seed, true)?;
+    world.spawn_rate = 0.0;
+    world.player.hp = i32::MAX / 2; // Must survive the whole run
+    let input = InputState { fire: true, aim: Aim::At(playfield::center()), ..InputState::default() };
+
+    let mut total = Duration::ZERO;
+    let mut worst = Duration::ZERO;
+    let mut steady_allocations = 0;
+    for tick in 0..BENCH_TICKS {
+        top_up(&mut world);
+        world.spawn_rate = 0.0; // Level ups raise it, but top_up does all the spawning
+        let allocations_before = allocations();
+        let start = Instant::now();
+        world.update(FIXED_DT, &input);
+        let elapsed = start.elapsed();
+        if tick >= BENCH_WARMUP_TICKS {
+            steady_allocations += allocations() - allocations_before;
+        }
+        total += elapsed;
+        worst = worst.max(elapsed);
+    }
+
+    let average = total / BENCH_TICKS as u32;
+    let budget = Duration::from_secs_f32(FIXED_DT);
+    println!("{} ticks with {} enemies and {} bullets", BENCH_TICKS, BENCH_ENEMIES, BENCH_ENEMY_BULLETS + BENCH_PLAYER_BULLETS);
+    println!("Average tick: {:.3} ms, worst: {:.3} ms, budget: {:.3} ms",
+        average.as_secs_f64() * 1000.0, worst.as_secs_f64() * 1000.0, budget.as_secs_f64() * 1000.0);
+    println!("Allocations after warm-up: {} in {} ticks, bullets dropped by full pools: {}",
+        steady_allocations, BENCH_TICKS - BENCH_WARMUP_TICKS, world.bullets.dropped());
+    if average >= budget {
+        return Err(GameError::CustomError("TOO SLOW for 60 Hz".to_string()));
+    }
+    if steady_allocations > 0 {
+        return Err(GameError::CustomError(format!("{} allocations after warm-up, a tick should make none", steady_allocations)));
+    }
+    println!("OK: fits in a 60 Hz frame without allocating");
+    Ok(())
+}
+
+// The pattern preview fires at a target this far below the middle of the
+// playfield, with the bullet stats of a shooter on level 1
+const PREVIEW_TARGET_DISTANCE: f32 = 300.0;
+const PREVIEW_DAMAGE: i32 = 1;
+const PREVIEW_BULLET_SPEED: f32 = 240.0;
+const PREVIEW_BULLET_SIZE: f32 = 10.0;
+
+// Fires one pattern from the middle of the playfield and prints the center of
+// every bullet each frame as CSV, until the pattern is done and its bullets
+// are gone or `frames` run out
+pub fn preview_pattern(id: &str, frames: u64, seed: u64, level: i32) -> GameResult<()> {
+    let library = PatternLibrary::load()?;
+    let pattern = library.find(id)
+        .ok_or_else(|| GameError::ResourceLoadError(format!("resources/patterns.toml has no pattern {}", id)))?;
+
+    let mut rng = GameRng::seed_from_u64(seed);
+    let mut bullets = BulletPool::with_capacity(BULLET_CAPACITY);
+    let (mut emitter, mut bursts) = (Emitter::new(), Emitter::new());
+    let mut bursting = Vec::new();
+    let origin = playfield::center();
+    let target = origin + na::Vector2::new(0.0, PREVIEW_TARGET_DISTANCE);
+    emitter.start(pattern, ShotStats { damage: PREVIEW_DAMAGE, speed: PREVIEW_BULLET_SPEED, size: PREVIEW_BULLET_SIZE, level });
+
+    // Same order as a world tick: fire, move, expire. Homing bullets turn towards the target.
+    println!("frame,bullet,x,y");
+    for frame in 0..frames {
+        emitter.update(&library, FIXED_DT, origin, target, &mut bullets, &mut rng);
+        for bullet in bullets.values_mut() {
+            bullet.update(FIXED_DT, Some(target));
+        }
+        expire_bullets(&bullets, &mut bursts, &mut bursting);
+        for id in bursting.drain(..) {
+            bullets.remove(id);
+        }
+        bursts.update(&library, FIXED_DT, origin, target, &mut bullets, &mut rng);
+        bullets.retain(|_, bullet| !bullet.is_off_screen());
+
+        for (id, bullet) in bullets.iter() {
+            let center = bullet.center();
+            println!("{},{},{:.1},{:.1}", frame, id, center.x, center.y);
+        }
+        if !emitter.is_firing() && !bursts.is_firing() && bullets.len() == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
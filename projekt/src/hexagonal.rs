@@ -1,84 +1,82 @@
-use ggez::{Context, GameResult};
-use ggez::graphics::{self, DrawParam, Image};
+use ggez::GameResult;
+use ggez::graphics::DrawParam;
 use nalgebra as na;
 use crate::player::Player;
-use crate::bullet::Bullet;
+use crate::bullet::BulletPool;
+use crate::pattern::{Emitter, ShotStats};
 use crate::enemy::Enemy;
+use crate::render::Renderer;
+use crate::catalogue::{EnemyCatalogue, EnemyDef};
+use crate::collider::Collider;
+use crate::world::{interpolate, GameRng};
 
 pub struct HexagonEnemy {
-    pub size: f32,
     pub pos: na::Point2<f32>,
+    pub prev_pos: na::Point2<f32>,
     pub hp: i32,
     pub speed: f32,
     pub damage: i32,
-    pub bullet_speed: f32,
-    pub last_shot_time: f32,
+    pub shot: ShotStats,
+    pub pattern: usize,
+    pub emitter: Emitter,
+    pub shot_timer: f32,
     pub shoot_cooldown: f32,
     pub coins: i32,
     pub points: i32,
-    pub sprite: Image
+    pub collider: Collider,
+    pub archetype: usize,
+    pub sprite_scale: f32,
 }
 
 impl HexagonEnemy {
-    pub fn new(pos: na::Point2<f32>, level: i32, image: Image) -> Self {
+    pub fn new(pos: na::Point2<f32>, level: i32, def: &EnemyDef, archetype: usize) -> Self {
         HexagonEnemy {
-            size: 62.5,
             pos,
-            hp: 3 * level,
-            speed: 3.0 * level as f32 / 2.0 ,
-            damage: 2 * level,
-            bullet_speed: 4.0 * level as f32,
-            last_shot_time: 0.0,
-            shoot_cooldown: 3.5,
-            coins: 100 * level,
-            points: 50 * level,
-            sprite: image
+            prev_pos: pos,
+            hp: def.hp.at_i32(level),
+            speed: def.speed.at(level),
+            damage: def.damage.at_i32(level),
+            shot: ShotStats { damage: def.damage.at_i32(level), speed: def.bullet_speed.at(level), size: def.bullet_size, level },
+            pattern: def.pattern_index.expect("shooters are checked for a pattern when loading"),
+            emitter: Emitter::new(),
+            shot_timer: 0.0,
+            shoot_cooldown: def.shoot_cooldown,
+            coins: def.coins.at_i32(level),
+            points: def.points.at_i32(level),
+            collider: def.collider(),
+            archetype,
+            sprite_scale: def.sprite_scale,
         }
     }
 
-    fn move_towards_player(&mut self, player_pos: &na::Point2<f32>) {
+    fn move_towards_player(&mut self, player_pos: &na::Point2<f32>, dt: f32) {
         let direction = (player_pos - self.pos).normalize();
-        self.pos += direction * self.speed;
+        self.pos += direction * self.speed * dt;
     }
-
-    // Shooting one bullet at a time towards player
-    fn shoot(&self, _ctx: &mut Context, target: na::Point2<f32>) -> GameResult<Vec<Bullet>> {
-        let mut bullets = Vec::new();
-        let bullet = Bullet::new(self.pos, target, self.bullet_speed, self.damage, 10.0);
-        bullets.push(bullet);
-        Ok(bullets)
-    }    
 }
 
 impl Enemy for HexagonEnemy {
-    fn update(&mut self, player: &Player, ctx: &mut Context, game_bullets: &mut Vec<Bullet>) {
-        self.move_towards_player(&player.player_pos);
-        
-        let current_time = ggez::timer::time_since_start(ctx).as_secs_f32();
-        let time_between_shots = self.shoot_cooldown; // Shoot every 2 seconds
+    fn update(&mut self, player: &Player, dt: f32, game_bullets: &mut BulletPool, catalogue: &EnemyCatalogue, rng: &mut GameRng) {
+        self.prev_pos = self.pos;
+        self.move_towards_player(&player.player_pos, dt);
 
-        if current_time - self.last_shot_time > time_between_shots {
-            // Destructing match result to get Ok(bullets) shoot retursns ok => then bullets else Error
-            if let Ok(bullets) = self.shoot(ctx, player.player_pos) {
-                game_bullets.extend(bullets); // Add bullets to the game's bullets list
-            }
-            self.last_shot_time = current_time; // Update the last shot time
+        self.shot_timer += dt;
+        if self.shot_timer > self.shoot_cooldown {
+            self.emitter.start(self.pattern, self.shot);
+            self.shot_timer = 0.0; // Reset the time since last shot
         }
+        // Add bullets to the game's bullets pool
+        self.emitter.update(&catalogue.patterns, dt, self.collider.center(&self.pos), player.player_pos, game_bullets, rng);
     }
 
-    fn draw(&self, ctx: &mut Context) -> GameResult {
-        graphics::draw(ctx, &self.sprite, DrawParam::default().dest([self.pos.x, self.pos.y]).scale([2.5, 2.5]))?;
+    fn draw(&self, _catalogue: &EnemyCatalogue, renderer: &mut Renderer, alpha: f32) -> GameResult {
+        let pos = interpolate(&self.prev_pos, &self.pos, alpha);
+        renderer.enemy(self.archetype, DrawParam::default().dest([pos.x, pos.y]).scale([self.sprite_scale, self.sprite_scale]));
         Ok(())
     }
 
-    fn check_collision(&self, player: &Player) -> bool {
-        let enemy_rect = graphics::Rect::new(self.pos.x - 10.0, self.pos.y - 10.0, self.size, self.size);
-        let player_rect = graphics::Rect::new(player.player_pos.x - 10.0, player.player_pos.y - 10.0, 20.0, 20.0);
-        enemy_rect.overlaps(&player_rect)
-    }
-
-    fn apply_damage(&self, player: &mut Player) {
-        player.take_damage(self.damage);
+    fn contact_damage(&self) -> i32 {
+        self.damage
     }
 
     fn take_damage(&mut self, damage: i32) -> i32 {
@@ -89,6 +87,10 @@ impl Enemy for HexagonEnemy {
         self.hp
     }
 
+    fn archetype(&self) -> usize {
+        self.archetype
+    }
+
     fn get_pos(&self) -> &na::Point2<f32> {
         &self.pos
     }
@@ -105,7 +107,7 @@ impl Enemy for HexagonEnemy {
         self.points
     }
 
-    fn get_size(&self) -> f32 {
-        self.size
+    fn collider(&self) -> &Collider {
+        &self.collider
     }
 }
\ No newline at end of file
@@ -0,0 +1,129 @@
+use ggez::Context;
+use ggez::event::Axis;
+use ggez::input::gamepad;
+use nalgebra as na;
+
+use crate::bindings::{Action, Bindings};
+
+// Where the player points: at a spot in the world with the mouse, or in a
+// direction with the right stick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aim {
+    At(na::Point2<f32>),
+    Towards(na::Vector2<f32>), // Unit length
+}
+
+impl Aim {
+    // A point to shoot at from `from`
+    pub fn target(&self, from: &na::Point2<f32>) -> na::Point2<f32> {
+        match self {
+            Aim::At(target) => *target,
+            Aim::Towards(direction) => from + direction,
+        }
+    }
+
+    // Facing angle of something at `from`, 0 is to the right
+    pub fn angle(&self, from: &na::Point2<f32>) -> f32 {
+        let direction = match self {
+            Aim::At(target) => target - from,
+            Aim::Towards(direction) => *direction,
+        };
+        direction.y.atan2(direction.x)
+    }
+}
+
+// Weapon change asked for this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeaponSwitch {
+    Slot(usize), // Number key, counting from 0
+    Next,
+    Previous,
+}
+
+// Snapshot of everything the simulation reads from the player in one frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputState {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub stick: na::Vector2<f32>, // Left stick past its deadzone, at most 1 long
+    pub fire: bool,
+    pub bomb: bool,
+    pub buy: Option<usize>, // Upgrade bought, only in the zero length steps the shop screen records
+    pub undo: bool, // Refund of the last purchase, also from the shop screen
+    pub weapon: Option<WeaponSwitch>, // Pressed this frame, not held
+    pub aim: Aim,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState {
+            up: false,
+            down: false,
+            left: false,
+            right: false,
+            stick: na::Vector2::zeros(),
+            fire: false,
+            bomb: false,
+            buy: None,
+            undo: false,
+            weapon: None,
+            aim: Aim::At(na::Point2::new(0.0, 0.0)),
+        }
+    }
+}
+
+impl InputState {
+    // The right stick aims while it is pushed, otherwise `aim` is kept: the
+    // last mouse position in world coordinates or the stick's last direction
+    pub fn capture(ctx: &Context, bindings: &Bindings, aim: Aim) -> InputState {
+        let down = |action| bindings.is_down(ctx, action);
+        let deadzones = bindings.deadzones;
+        let aim = match stick(ctx, Axis::RightStickX, Axis::RightStickY, deadzones.aim) {
+            Some(direction) => Aim::Towards(direction.normalize()),
+            None => aim,
+        };
+        InputState {
+            up: down(Action::MoveUp),
+            down: down(Action::MoveDown),
+            left: down(Action::MoveLeft),
+            right: down(Action::MoveRight),
+            stick: stick(ctx, Axis::LeftStickX, Axis::LeftStickY, deadzones.movement).unwrap_or_else(na::Vector2::zeros),
+            fire: down(Action::Fire),
+            bomb: down(Action::Bomb),
+            aim,
+            ..InputState::default()
+        }
+    }
+
+    fn buttons_mut(&mut self) -> impl Iterator<Item = &mut bool> {
+        [&mut self.up, &mut self.down, &mut self.left, &mut self.right, &mut self.fire, &mut self.bomb].into_iter()
+    }
+
+    // Ignores buttons that were already down in `held` until they are let go,
+    // so a key that changed screens doesn't also act in the game
+    pub fn mask_held(&mut self, held: &mut InputState) {
+        for (button, was_held) in self.buttons_mut().zip(held.buttons_mut()) {
+            if !*button {
+                *was_held = false;
+            }
+            if *was_held {
+                *button = false;
+            }
+        }
+    }
+}
+
+// The first gamepad stick pushed past `deadzone`, in world directions (y down).
+// Rescaled so the edge of the deadzone is 0 and full tilt is 1, which keeps
+// slow movement possible right past the deadzone.
+fn stick(ctx: &Context, x: Axis, y: Axis, deadzone: f32) -> Option<na::Vector2<f32>> {
+    gamepad::gamepads(ctx)
+        .map(|(_, pad)| na::Vector2::new(pad.value(x), -pad.value(y)))
+        .find(|tilt| tilt.norm() > deadzone)
+        .map(|tilt| {
+            let length = tilt.norm();
+            tilt / length * ((length.min(1.0) - deadzone) / (1.0 - deadzone))
+        })
+}
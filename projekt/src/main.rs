@@ -7,23 +7,79 @@ mod triangle;
 mod hexagonal;
 mod boss;
 mod menu;
+mod input;
+mod sprites;
+mod world;
+mod headless;
+mod cli;
+mod replay;
+mod resources;
+mod catalogue;
+mod waves;
+mod spatial;
+mod collider;
+mod arena;
+mod alloc_counter;
+mod render;
+mod playfield;
+mod camera;
+mod screen;
+mod bindings;
+mod stats;
+mod bomb;
+mod pattern;
+mod weapon;
 
 use ggez::{ContextBuilder, GameResult};
 use ggez::event;
 use game::Game;
+use cli::Options;
+use replay::Replay;
+use alloc_counter::CountingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 fn main() -> GameResult {
+    let options = Options::parse()?;
+
+    if options.bench {
+        return headless::benchmark(options.seed);
+    }
+    if let Some(pattern) = &options.pattern {
+        return headless::preview_pattern(pattern, options.simulate.unwrap_or(600), options.seed, options.level);
+    }
+
+    let replay = match &options.replay {
+        Some(path) => Some(Replay::load(path)?),
+        None => None,
+    };
+    if let Some(replay) = &replay {
+        if !replay.is_current_version() {
+            println!("Warning: replay was recorded with version {}, playback may differ", replay.version);
+        }
+    }
+
+    if let Some(frames) = options.simulate {
+        match &replay {
+            Some(replay) => headless::replay(replay)?,
+            None => headless::simulate(frames, options.seed, options.endless, options.record.as_deref())?,
+        };
+        return Ok(());
+    }
+
     let (mut ctx, event_loop) = ContextBuilder::new("bullet_hell", "BoomBoom")
         .window_setup(ggez::conf::WindowSetup::default().title("Bullet Hell"))
         .window_mode(ggez::conf::WindowMode {
-            width: 1500.0,
-            height: 1000.0,
+            width: playfield::WIDTH,
+            height: playfield::HEIGHT,
+            resizable: true,
             ..Default::default()
         })
         .add_resource_path("resources")
         .build()?;
-    
-    let game = Game::new(&mut ctx)?;
-    
+
+    let game = Game::new(&mut ctx, &options, replay)?;
+
     event::run(ctx, event_loop, game)
 }
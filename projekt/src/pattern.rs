@@ -0,0 +1,431 @@
+use ggez::{GameError, GameResult};
+use nalgebra as na;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::arena::Id;
+use crate::bullet::{Bullet, BulletPool, Burst, Motion, Team};
+use crate::catalogue::Scaled;
+use crate::resources::load_toml;
+use crate::world::GameRng;
+
+// How one volley is laid out around the aim
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    Ring,   // Evenly around the whole circle, starting at the aim
+    Fan,    // Evenly across `arc` degrees, centered on the aim
+    Spread, // Random angles within `arc` degrees and random speeds
+}
+
+// What the aim points at before `angle` is added
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AimMode {
+    Player, // The player's position when the volley is fired
+    Fixed,  // Angle 0, to the right
+}
+
+// One pattern as written in resources/patterns.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct PatternDef {
+    pub id: String,
+    pub shape: Shape,
+    #[serde(default = "default_aim")]
+    pub aim: AimMode,
+    #[serde(default)]
+    pub angle: f32, // Degrees, 90 is down
+    #[serde(default)]
+    pub arc: f32,
+    #[serde(default = "default_count")]
+    pub count: Scaled,
+    #[serde(default = "default_volleys")]
+    pub volleys: u32,
+    #[serde(default)]
+    pub interval: f32,
+    #[serde(default)]
+    pub spin: f32, // Degrees per volley
+    #[serde(default = "default_multiplier")]
+    pub speed: f32,
+    #[serde(default)]
+    pub speed_spread: f32,
+    #[serde(default = "default_multiplier")]
+    pub size: f32,
+    #[serde(default = "default_multiplier")]
+    pub damage: f32,
+    #[serde(flatten)]
+    pub motion: Motion,
+    pub on_expire: Option<String>, // Pattern every bullet bursts into at the end of its lifetime
+    // `on_expire` as a pattern index, filled in when loading
+    #[serde(skip)]
+    pub burst: Option<usize>,
+}
+
+fn default_aim() -> AimMode {
+    AimMode::Player
+}
+
+fn default_count() -> Scaled {
+    Scaled { base: 1.0, per_level: 0.0 }
+}
+
+fn default_volleys() -> u32 {
+    1
+}
+
+fn default_multiplier() -> f32 {
+    1.0
+}
+
+// Contents of resources/patterns.toml
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct PatternLibrary {
+    pub patterns: Vec<PatternDef>,
+}
+
+impl PatternLibrary {
+    pub fn load() -> GameResult<PatternLibrary> {
+        let library: PatternLibrary = load_toml("patterns.toml")?;
+        library.resolve()
+    }
+
+    // Checks the patterns and turns their on_expire ids into indices
+    fn resolve(self) -> GameResult<PatternLibrary> {
+        let mut library = self;
+        let error = |message: String| GameError::ResourceLoadError(format!("resources/patterns.toml: {}", message));
+
+        for (i, def) in library.patterns.iter().enumerate() {
+            if library.patterns[..i].iter().any(|other| other.id == def.id) {
+                return Err(error(format!("{} is listed twice", def.id)));
+            }
+            if def.volleys == 0 || (def.volleys > 1 && def.interval <= 0.0) {
+                return Err(error(format!("{} needs at least one volley and a positive interval between them", def.id)));
+            }
+            let motion = &def.motion;
+            if motion.lifetime <= 0.0 || motion.max_speed <= 0.0 || motion.homing < 0.0 || motion.delay < 0.0 {
+                return Err(error(format!("{} needs a positive lifetime and max_speed and no negative homing or delay", def.id)));
+            }
+            if def.on_expire.is_some() && motion.lifetime == f32::INFINITY {
+                return Err(error(format!("{} bursts on expiry but has no lifetime", def.id)));
+            }
+        }
+
+        let ids: Vec<String> = library.patterns.iter().map(|def| def.id.clone()).collect();
+        for def in &mut library.patterns {
+            if let Some(pattern) = &def.on_expire {
+                match ids.iter().position(|id| id == pattern) {
+                    Some(i) => def.burst = Some(i),
+                    None => return Err(error(format!("{} bursts into unknown pattern {}", def.id, pattern))),
+                }
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn find(&self, id: &str) -> Option<usize> {
+        self.patterns.iter().position(|def| def.id == id)
+    }
+}
+
+// What the owner brings to every pattern it fires
+#[derive(Clone, Copy, Debug)]
+pub struct ShotStats {
+    pub damage: i32,
+    pub speed: f32,
+    pub size: f32,
+    pub level: i32, // For counts that grow with the level
+}
+
+// A pattern being fired, one volley every interval
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    pattern: usize,
+    volley: u32,
+    next_volley: f32,
+    origin: Option<na::Point2<f32>>, // Fixed spot, or the owner's position when None
+    stats: ShotStats,
+}
+
+// Patterns an enemy's emitter has room for at once before it has to grow
+const RUNS_PER_EMITTER: usize = 4;
+
+// Fires patterns from the library. Enemies own one that follows them, the
+// world owns one for child bullets bursting at fixed spots.
+pub struct Emitter {
+    runs: Vec<Run>,
+}
+
+impl Default for Emitter {
+    fn default() -> Emitter {
+        Emitter::new()
+    }
+}
+
+impl Emitter {
+    // Room is made when the owner spawns, not on its first shot
+    pub fn new() -> Emitter {
+        Emitter::with_capacity(RUNS_PER_EMITTER)
+    }
+
+    pub fn with_capacity(runs: usize) -> Emitter {
+        Emitter { runs: Vec::with_capacity(runs) }
+    }
+
+    // Fires from the owner's position, wherever it moves
+    pub fn start(&mut self, pattern: usize, stats: ShotStats) {
+        self.runs.push(Run { pattern, volley: 0, next_volley: 0.0, origin: None, stats });
+    }
+
+    pub fn start_at(&mut self, pattern: usize, origin: na::Point2<f32>, stats: ShotStats) {
+        self.runs.push(Run { pattern, volley: 0, next_volley: 0.0, origin: Some(origin), stats });
+    }
+
+    pub fn stop(&mut self) {
+        self.runs.clear();
+    }
+
+    pub fn is_firing(&self) -> bool {
+        !self.runs.is_empty()
+    }
+
+    // Fires the volleys that are due this tick
+    pub fn update(&mut self, library: &PatternLibrary, dt: f32, owner: na::Point2<f32>, target: na::Point2<f32>,
+                  bullets: &mut BulletPool, rng: &mut GameRng) {
+        for run in &mut self.runs {
+            run.next_volley -= dt;
+            if run.next_volley > 0.0 {
+                continue;
+            }
+            let def = &library.patterns[run.pattern];
+            fire_volley(def, run.volley, run.origin.unwrap_or(owner), target, &run.stats, bullets, rng);
+            run.volley += 1;
+            run.next_volley += def.interval;
+        }
+        self.runs.retain(|run| run.volley < library.patterns[run.pattern].volleys);
+    }
+}
+
+fn fire_volley(def: &PatternDef, volley: u32, origin: na::Point2<f32>, target: na::Point2<f32>, stats: &ShotStats,
+               bullets: &mut BulletPool, rng: &mut GameRng) {
+    let aim = match def.aim {
+        AimMode::Player => (target.y - origin.y).atan2(target.x - origin.x),
+        AimMode::Fixed => 0.0,
+    } + (def.angle + def.spin * volley as f32).to_radians();
+    let count = def.count.at_i32(stats.level).max(1) as usize;
+    let arc = def.arc.to_radians();
+
+    let size = stats.size * def.size;
+    let damage = (stats.damage as f32 * def.damage).round() as i32;
+    let on_expire = def.burst.map(|pattern| Burst { pattern, stats: *stats });
+    // Bullet positions are their top-left corner, this puts their middle on the origin
+    let start = origin - na::Vector2::new(size / 2.0, size / 2.0);
+
+    for i in 0..count {
+        let (angle, speed_factor) = match def.shape {
+            Shape::Ring => (aim + i as f32 * std::f32::consts::TAU / count as f32, 1.0),
+            Shape::Fan if count == 1 => (aim, 1.0),
+            Shape::Fan => (aim - arc / 2.0 + i as f32 * arc / (count - 1) as f32, 1.0),
+            Shape::Spread => {
+                let angle = aim + rng.gen_range(-0.5..=0.5) * arc;
+                (angle, 1.0 + rng.gen_range(-1.0..=1.0) * def.speed_spread)
+            }
+        };
+        let direction = na::Vector2::new(angle.cos(), angle.sin());
+        let mut bullet = Bullet::new(Team::Enemy, start, start + direction, stats.speed * def.speed * speed_factor, damage, size)
+            .with_motion(def.motion);
+        bullet.on_expire = on_expire;
+        bullets.insert(bullet);
+    }
+}
+
+// Queues the bullets whose lifetime ran out in `despawn`, and starts the
+// pattern of those that burst where they are
+pub fn expire_bullets(bullets: &BulletPool, bursts: &mut Emitter, despawn: &mut Vec<Id>) {
+    for (id, bullet) in bullets.iter() {
+        if !bullet.is_expired() {
+            continue;
+        }
+        if let Some(burst) = bullet.on_expire {
+            bursts.start_at(burst.pattern, bullet.center(), burst.stats);
+        }
+        despawn.push(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bullet::BULLET_CAPACITY;
+    use crate::world::FIXED_DT;
+
+    const STATS: ShotStats = ShotStats { damage: 2, speed: 100.0, size: 10.0, level: 1 };
+
+    fn library(patterns: &str) -> PatternLibrary {
+        let library: PatternLibrary = toml::from_str(patterns).unwrap();
+        library.resolve().unwrap()
+    }
+
+    // Bullet headings in degrees from 0 to 360, in the order they were fired
+    fn headings(bullets: &BulletPool) -> Vec<f32> {
+        bullets.values().map(|bullet| bullet.heading.to_degrees().rem_euclid(360.0)).collect()
+    }
+
+    fn assert_headings(bullets: &BulletPool, expected: &[f32]) {
+        let headings = headings(bullets);
+        assert_eq!(headings.len(), expected.len(), "{:?}", headings);
+        for (heading, expected) in headings.iter().zip(expected) {
+            assert!((heading - expected).abs() < 1e-3, "{:?} instead of {:?}", headings, expected);
+        }
+    }
+
+    // Fires the first pattern from the origin at a target straight below it
+    // for `seconds`, the world's fire, move and burst steps without the world
+    fn fire(library: &PatternLibrary, seconds: f32) -> (BulletPool, Emitter) {
+        let mut rng = GameRng::seed_from_u64(5);
+        let mut bullets = BulletPool::with_capacity(BULLET_CAPACITY);
+        let (mut emitter, mut bursts) = (Emitter::new(), Emitter::new());
+        let mut expired = Vec::new();
+        let (origin, target) = (na::Point2::new(500.0, 500.0), na::Point2::new(500.0, 600.0));
+        emitter.start(0, STATS);
+        for _ in 0..(seconds / FIXED_DT).round() as usize {
+            emitter.update(library, FIXED_DT, origin, target, &mut bullets, &mut rng);
+            for bullet in bullets.values_mut() {
+                bullet.update(FIXED_DT, None);
+            }
+            expire_bullets(&bullets, &mut bursts, &mut expired);
+            for id in expired.drain(..) {
+                bullets.remove(id);
+            }
+            bursts.update(library, FIXED_DT, origin, target, &mut bullets, &mut rng);
+        }
+        (bullets, emitter)
+    }
+
+    #[test]
+    fn ring_goes_around_from_the_aim() {
+        let library = library(r#"
+            [[patterns]]
+            id = "ring"
+            shape = "ring"
+            aim = "fixed"
+            angle = 45
+            count = { base = 4 }
+            damage = 1.5
+            size = 2
+        "#);
+        let (bullets, emitter) = fire(&library, FIXED_DT);
+
+        assert_headings(&bullets, &[45.0, 135.0, 225.0, 315.0]);
+        assert!(!emitter.is_firing());
+        for bullet in bullets.values() {
+            assert_eq!((bullet.team, bullet.damage, bullet.size, bullet.speed), (Team::Enemy, 3, 20.0, 100.0));
+        }
+    }
+
+    #[test]
+    fn fan_is_centered_on_the_player() {
+        let fan = r#"
+            [[patterns]]
+            id = "fan"
+            shape = "fan"
+            arc = 90
+            count = { base = 3, per_level = 2 }
+        "#;
+        let (bullets, _) = fire(&library(fan), FIXED_DT);
+        assert_headings(&bullets, &[45.0, 90.0, 135.0]);
+
+        // Counts grow with the level of the shooter
+        let mut bullets = BulletPool::with_capacity(BULLET_CAPACITY);
+        let def = &library(fan).patterns[0];
+        let stats = ShotStats { level: 2, ..STATS };
+        fire_volley(def, 0, na::Point2::origin(), na::Point2::new(0.0, 1.0), &stats, &mut bullets, &mut GameRng::seed_from_u64(1));
+        assert_headings(&bullets, &[45.0, 67.5, 90.0, 112.5, 135.0]);
+    }
+
+    #[test]
+    fn spread_stays_within_its_arc_and_speeds() {
+        let library = library(r#"
+            [[patterns]]
+            id = "spread"
+            shape = "spread"
+            arc = 60
+            count = { base = 200 }
+            speed_spread = 0.25
+        "#);
+        let (bullets, _) = fire(&library, FIXED_DT);
+
+        assert_eq!(bullets.len(), 200);
+        for bullet in bullets.values() {
+            let heading = bullet.heading.to_degrees();
+            assert!((60.0..=120.0).contains(&heading), "heading {}", heading);
+            assert!((75.0..=125.0).contains(&bullet.speed), "speed {}", bullet.speed);
+        }
+        // Seeded, but not all the same
+        assert!(headings(&bullets).windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn volleys_come_every_interval_and_spin() {
+        let library = library(r#"
+            [[patterns]]
+            id = "spiral"
+            shape = "fan"
+            aim = "fixed"
+            volleys = 3
+            interval = 0.5
+            spin = 30
+        "#);
+
+        let (bullets, emitter) = fire(&library, 0.25);
+        assert_headings(&bullets, &[0.0]);
+        assert!(emitter.is_firing());
+        let (bullets, _) = fire(&library, 0.75);
+        assert_headings(&bullets, &[0.0, 30.0]);
+        let (bullets, emitter) = fire(&library, 1.25);
+        assert_headings(&bullets, &[0.0, 30.0, 60.0]);
+        assert!(!emitter.is_firing());
+    }
+
+    #[test]
+    fn expired_bullets_burst_where_they_are() {
+        let library = library(r#"
+            [[patterns]]
+            id = "shell"
+            shape = "fan"
+            lifetime = 0.5
+            on_expire = "shrapnel"
+
+            [[patterns]]
+            id = "shrapnel"
+            shape = "ring"
+            aim = "fixed"
+            count = { base = 4 }
+        "#);
+        assert_eq!(library.patterns[0].burst, Some(1));
+
+        let (bullets, _) = fire(&library, 0.45);
+        assert_headings(&bullets, &[90.0]);
+        let (bullets, _) = fire(&library, 0.6);
+        assert_headings(&bullets, &[0.0, 90.0, 180.0, 270.0]);
+        // Centered on where the shell ran out, 100 pixels per second for half a second below the origin
+        for bullet in bullets.values() {
+            let age = bullet.age;
+            let center = bullet.center() - na::Vector2::new(bullet.heading.cos(), bullet.heading.sin()) * bullet.speed * age;
+            assert!((center - na::Point2::new(500.0, 550.0)).norm() < 2.0, "burst from {}", center);
+        }
+    }
+
+    #[test]
+    fn unknown_bursts_are_refused() {
+        let library: PatternLibrary = toml::from_str(r#"
+            [[patterns]]
+            id = "shell"
+            shape = "fan"
+            lifetime = 0.5
+            on_expire = "nothing"
+        "#).unwrap();
+        assert!(library.resolve().is_err());
+    }
+}
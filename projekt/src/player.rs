@@ -1,43 +1,136 @@
+use std::f32::consts::TAU;
+
 use ggez::{Context, GameResult};
-use ggez::input::{keyboard, mouse};
 use nalgebra as na;
-use std::time::{Duration, Instant};
-use crate::bullet::Bullet;
-use ggez::graphics::{self, DrawParam, Color, Mesh, Image};
+use crate::bullet::{Bullet, BulletPool, Motion, Team};
+use crate::input::{Aim, InputState, WeaponSwitch};
+use crate::world::interpolate;
+use crate::collider::Collider;
+use crate::render::Renderer;
+use crate::playfield;
+use ggez::graphics::{self, DrawParam, Color};
+use crate::stats::{Modifier, Source, Stat, Stats};
+use crate::bomb::{MAX_BOMBS, STARTING_BOMBS};
+use crate::weapon::{Beam, Kind, Weapon, WeaponLibrary};
 
+// Seconds the player can't be hurt again after a hit
+const INVULNERABLE_TIME: f32 = 0.8;
+// The sprite blinks on and off this often while invulnerable
+const BLINK_PERIOD: f32 = 0.1;
+// How fast knockback dies down, per second
+const KNOCKBACK_DRAG: f32 = 8.0;
+// Seconds before a bullet disappears, so homing bullets can't circle forever
+const BULLET_LIFETIME: f32 = 3.0;
+// Size of a drone as drawn
+const DRONE_RADIUS: f32 = 8.0;
 
 pub struct Player {
-    pub hp: i32,
-    pub speed: f32,
-    pub damage: i32,
+    pub hp: i32, // Current HP, at most the MaxHp stat
+    pub stats: Stats,
     pub player_pos: na::Point2<f32>,
-    pub bullets: Vec<Bullet>,
-    pub last_shot_time: Instant,
-    pub fire_rate: f32,
-    pub player_bullet_speed: f32,
+    pub prev_pos: na::Point2<f32>,
+    pub collider: Collider,
+    pub time_since_shot: f32,
+    pub invulnerable: f32, // Seconds of invulnerability left
+    pub knockback: na::Vector2<f32>, // Push from the last hit, fades out
+    pub bombs: u32, // Bomb charges left, at most MAX_BOMBS
+    pub weapons: Vec<Weapon>, // Every weapon in weapons.toml, in the order of the number keys
+    pub weapon: usize, // The one in hand
+    burst_left: u32, // Shots of the last trigger pull still to come
+    burst_timer: f32, // Seconds until the next of them
+    pub drone_angle: f32, // Degrees the drones have turned around the player
+    pub beam: Option<Beam>, // The laser this tick, while it is held
     pub coins: i32,
     pub points: i32,
-    pub player_image: Image,
 }
 
 impl Player {
-    pub fn new(image: Image) -> GameResult<Player> {
+    // sprite_size is the player image size, the sprite is drawn centered on player_pos
+    pub fn new(sprite_size: [f32; 2], weapons: &WeaponLibrary) -> GameResult<Player> {
         let s = Player {
             hp: 100,
-            damage: 1,
-            speed: 5.0,
-            player_pos: na::Point2::new(400.0, 300.0),
-            bullets: Vec::new(),
-            last_shot_time: Instant::now(),
-            fire_rate: 0.6,
-            player_bullet_speed: 15.0,
+            stats: Stats::new(&[
+                (Stat::MaxHp, 100.0),
+                (Stat::Damage, 1.0),
+                (Stat::Speed, 300.0),
+                (Stat::FireRate, 0.6),
+                (Stat::BulletSpeed, 900.0),
+                (Stat::Homing, 0.0),
+                (Stat::Pierce, 0.0),
+            ]),
+            player_pos: playfield::center(),
+            prev_pos: playfield::center(),
+            // Smaller than the sprite so grazing the gun barrel doesn't count as a hit
+            collider: Collider::circle(sprite_size[0].min(sprite_size[1]) * 0.3),
+            time_since_shot: 0.0,
+            invulnerable: 0.0,
+            knockback: na::Vector2::zeros(),
+            bombs: STARTING_BOMBS,
+            weapons: weapons.weapons.iter().cloned().map(Weapon::new).collect(),
+            weapon: 0,
+            burst_left: 0,
+            burst_timer: 0.0,
+            drone_angle: 0.0,
+            beam: None,
             coins: 1000,
             points: 0,
-            player_image: image
         };
         Ok(s)
     }
 
+    pub fn max_hp(&self) -> i32 {
+        self.stats.get_i32(Stat::MaxHp)
+    }
+
+    // Healing never goes over max HP and can't bring the player back
+    pub fn heal(&mut self, amount: i32) {
+        if !self.is_dead() {
+            self.hp = (self.hp + amount).min(self.max_hp());
+        }
+    }
+
+    // Goes to the weapon's own stats when one is given
+    pub fn add_modifier(&mut self, modifier: Modifier, weapon: Option<usize>) {
+        if let Some(weapon) = weapon {
+            self.weapons[weapon].stats.add(modifier);
+            return;
+        }
+        let max_hp = self.max_hp();
+        self.stats.add(modifier);
+        self.max_hp_changed(max_hp);
+    }
+
+    pub fn remove_modifier(&mut self, source: Source, weapon: Option<usize>) -> bool {
+        if let Some(weapon) = weapon {
+            return self.weapons[weapon].stats.remove_last(source);
+        }
+        let max_hp = self.max_hp();
+        let removed = self.stats.remove_last(source);
+        self.max_hp_changed(max_hp);
+        removed
+    }
+
+    // Every modifier from `source`, on the player and on any weapon
+    #[allow(dead_code)]
+    pub fn remove_source(&mut self, source: Source) -> usize {
+        let max_hp = self.max_hp();
+        let removed = self.stats.remove_source(source)
+            + self.weapons.iter_mut().map(|weapon| weapon.stats.remove_source(source)).sum::<usize>();
+        self.max_hp_changed(max_hp);
+        removed
+    }
+
+    // Current HP moves with max HP, so raising it heals by the same amount
+    // and taking that back away again doesn't leave free health behind
+    fn max_hp_changed(&mut self, old_max_hp: i32) {
+        let change = self.max_hp() - old_max_hp;
+        if change > 0 {
+            self.heal(change);
+        } else if change < 0 && !self.is_dead() {
+            self.hp = (self.hp + change).max(1);
+        }
+    }
+
     pub fn take_damage(&mut self, damage: i32) {
         self.hp -= damage;
         if self.hp < 0 {
@@ -45,113 +138,303 @@ impl Player {
         }
     }
 
+    // Damage from an enemy or bullet at `from`. Starts a short invulnerability
+    // window and pushes the player away. False while still invulnerable.
+    pub fn hit(&mut self, damage: i32, from: &na::Point2<f32>, knockback: f32) -> bool {
+        if self.invulnerable > 0.0 || self.is_dead() {
+            return false;
+        }
+        self.take_damage(damage);
+        self.invulnerable = INVULNERABLE_TIME;
+
+        let away = self.player_pos - from;
+        if away.norm() > f32::EPSILON {
+            self.knockback = away.normalize() * knockback;
+        }
+        true
+    }
+
+    pub fn bounds(&self) -> graphics::Rect {
+        self.collider.bounds(&self.player_pos)
+    }
+
     pub fn check_possition(&mut self) {
-        if self.player_pos.x < 0.0 {
-            self.player_pos.x = 0.0;
-        } else if self.player_pos.x > 1570.0 {
-            self.player_pos.x = 1570.0;
+        self.player_pos = playfield::clamp(&self.player_pos);
+    }
+
+    pub fn weapon(&self) -> &Weapon {
+        &self.weapons[self.weapon]
+    }
+
+    // A burst in progress is dropped with the weapon it came from
+    fn switch_weapon(&mut self, switch: WeaponSwitch) {
+        let count = self.weapons.len();
+        let weapon = match switch {
+            WeaponSwitch::Slot(slot) if slot < count => slot,
+            WeaponSwitch::Slot(_) => return,
+            WeaponSwitch::Next => (self.weapon + 1) % count,
+            WeaponSwitch::Previous => (self.weapon + count - 1) % count,
+        };
+        if weapon != self.weapon {
+            self.weapon = weapon;
+            self.burst_left = 0;
         }
-        if self.player_pos.y < 0.0 {
-            self.player_pos.y = 0.0;
-        } else if self.player_pos.y > 1000.0 {
-            self.player_pos.y = 1000.0;
+    }
+
+    // Seconds between trigger pulls of the weapon in hand
+    pub fn cooldown(&self) -> f32 {
+        let weapon = self.weapon();
+        let cooldown = self.stats.get(Stat::FireRate) * weapon.def.fire_rate * weapon.stats.get(Stat::FireRate);
+        cooldown.max(Stat::FireRate.floor())
+    }
+
+    fn damage(&self) -> f32 {
+        let weapon = self.weapon();
+        self.stats.get(Stat::Damage) * weapon.def.damage * weapon.stats.get(Stat::Damage)
+    }
+
+    pub fn shot_damage(&self) -> i32 {
+        (self.damage().round() as i32).max(1)
+    }
+
+    // A laser deals the damage of a shot over the seconds between shots
+    pub fn laser_dps(&self) -> f32 {
+        self.damage() / self.cooldown()
+    }
+
+    fn shot_pierce(&self) -> u32 {
+        (self.stats.get_i32(Stat::Pierce) + self.weapon().stats.get_i32(Stat::Pierce)).max(0) as u32
+    }
+
+    // Drones of the weapon in hand around `center`, none for other weapons
+    pub fn drone_positions(&self, center: na::Point2<f32>) -> impl Iterator<Item = na::Point2<f32>> + '_ {
+        let weapon = self.weapon();
+        let count = match weapon.def.kind {
+            Kind::Drones => weapon.stats.get_i32(Stat::Projectiles).max(1) as usize,
+            _ => 0,
+        };
+        (0..count).map(move |i| {
+            let angle = self.drone_angle.to_radians() + i as f32 * TAU / count as f32;
+            center + na::Vector2::new(angle.cos(), angle.sin()) * weapon.def.orbit_radius
+        })
+    }
+
+    // `count` bullets from `origin` fanned across `spread` degrees around the `aim` angle
+    fn fire_bullets(&self, origin: na::Point2<f32>, aim: f32, count: u32, spread: f32, bullets: &mut BulletPool) {
+        let weapon = self.weapon();
+        let speed = self.stats.get(Stat::BulletSpeed) * weapon.def.speed;
+        let motion = Motion { homing: self.stats.get(Stat::Homing), lifetime: BULLET_LIFETIME, ..Motion::default() };
+        let spread = spread.to_radians();
+        for i in 0..count {
+            let angle = match count {
+                1 => aim,
+                _ => aim - spread / 2.0 + i as f32 * spread / (count - 1) as f32,
+            };
+            let towards = origin + na::Vector2::new(angle.cos(), angle.sin());
+            let bullet = Bullet::new(Team::Player, origin, towards, speed, self.shot_damage(), weapon.def.size)
+                .with_motion(motion)
+                .with_pierce(self.shot_pierce());
+            bullets.insert(bullet);
+        }
+    }
+
+    // One shot of the weapon in hand: bullets from the player or the drones
+    fn shoot(&mut self, target: na::Point2<f32>, bullets: &mut BulletPool) {
+        let weapon = self.weapon();
+        // Aimed from the player, so drones fire parallel to it. A stick's
+        // target is only a pixel away and would be behind some of them.
+        let aim = (target - self.player_pos).y.atan2((target - self.player_pos).x);
+        match weapon.def.kind {
+            Kind::Gun => {
+                let count = weapon.stats.get_i32(Stat::Projectiles).max(1) as u32;
+                self.fire_bullets(self.player_pos, aim, count, weapon.stats.get(Stat::Spread), bullets);
+            }
+            Kind::Drones => {
+                for drone in self.drone_positions(self.player_pos) {
+                    self.fire_bullets(drone, aim, 1, 0.0, bullets);
+                }
+            }
+            Kind::Laser => {} // Hurts every tick it is held instead
         }
     }
 
-    pub fn fire(&mut self, ctx: &mut Context) {
-        if self.last_shot_time.elapsed() >= Duration::from_secs_f32(self.fire_rate) {
-            let mouse_pos = mouse::position(ctx);
-            let bullet = Bullet::new(self.player_pos, na::Point2::new(mouse_pos.x, mouse_pos.y), self.player_bullet_speed, self.damage, 10.0);
-            self.bullets.push(bullet);
-            self.last_shot_time = Instant::now();  // Update the shot time
+    fn aim_beam(&self, target: na::Point2<f32>) -> Beam {
+        let def = &self.weapon().def;
+        let direction = target - self.player_pos;
+        Beam {
+            start: self.player_pos,
+            direction: direction.try_normalize(f32::EPSILON).unwrap_or_else(na::Vector2::x),
+            length: def.range,
+            width: def.size,
+            damage: self.laser_dps(),
+            pierce: self.shot_pierce(),
+            color: def.color(),
         }
     }
 
     fn draw_ui(&self, ctx: &mut Context) -> ggez::GameResult {
-        let text = format!("HP: {} \nPoints: {} \nDamage: {} \nFire Rate: {} \nSpeed: {} \nCoins: {}",
-            self.hp, self.points, self.damage, self.fire_rate, self.speed, self.coins);
+        let stats = &self.stats;
+        let damage = match self.weapon().def.kind {
+            Kind::Laser => format!("{:.1}/s", self.laser_dps()),
+            _ => self.shot_damage().to_string(),
+        };
+        let text = format!("HP: {}/{} \nPoints: {} \nWeapon: {} \nDamage: {} \nFire Rate: {:.2}s \nSpeed: {:.0} \nBombs: {}/{} \nCoins: {}",
+            self.hp, self.max_hp(), self.points, self.weapon().def.name, damage, self.cooldown(),
+            stats.get(Stat::Speed), self.bombs, MAX_BOMBS, self.coins);
 
         let display_text = graphics::Text::new((text, graphics::Font::default(), 30.0));
-        graphics::draw(ctx, &display_text, DrawParam::default().dest([10.0, 800.0]))?;
+        graphics::draw(ctx, &display_text, DrawParam::default().dest([10.0, 735.0]))?;
         Ok(())
     }
 
-    pub fn update(&mut self, ctx: &mut Context) -> GameResult {
-        // Player movement
-        let speed = self.speed;
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::W) {
-            self.player_pos.y -= speed;
+    // New shots go into the world's bullet pool
+    pub fn update(&mut self, input: &InputState, dt: f32, bullets: &mut BulletPool) {
+        self.time_since_shot += dt;
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+        self.prev_pos = self.player_pos;
+        if let Some(switch) = input.weapon {
+            self.switch_weapon(switch);
         }
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::S) {
-            self.player_pos.y += speed;
+
+        // Player movement, keys move at full speed and the stick anywhere up to it
+        let mut direction = input.stick;
+        if input.up {
+            direction.y -= 1.0;
         }
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::A) {
-            self.player_pos.x -= speed;
+        if input.down {
+            direction.y += 1.0;
         }
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::D) {
-            self.player_pos.x += speed;
+        if input.left {
+            direction.x -= 1.0;
         }
+        if input.right {
+            direction.x += 1.0;
+        }
+        self.player_pos += direction * (self.stats.get(Stat::Speed) * dt);
+        self.player_pos += self.knockback * dt;
+        self.knockback *= (-KNOCKBACK_DRAG * dt).exp();
 
         self.check_possition();
+        self.drone_angle = (self.drone_angle + self.weapon().def.orbit_speed * dt) % 360.0;
 
-        // Exit the game when ESC is pressed
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Escape) {
-            ggez::event::quit(ctx);
+        // The laser hurts for as long as it is held, it has no shots to wait for
+        let target = input.aim.target(&self.player_pos);
+        self.beam = None;
+        if self.weapon().def.kind == Kind::Laser {
+            if input.fire {
+                self.beam = Some(self.aim_beam(target));
+            }
+            return;
         }
 
-        // Fire when the spacebar is pressed
-        if keyboard::is_key_pressed(ctx, ggez::event::KeyCode::Space) || mouse::button_pressed(ctx, ggez::input::mouse::MouseButton::Left) {
-            self.fire(ctx);
+        // Fire when the spacebar, mouse button or trigger is held
+        let (burst, burst_interval) = (self.weapon().def.burst, self.weapon().def.burst_interval);
+        if input.fire && self.time_since_shot >= self.cooldown() {
+            self.time_since_shot = 0.0;
+            self.burst_left = burst - 1;
+            self.burst_timer = burst_interval;
+            self.shoot(target, bullets);
+        } else if self.burst_left > 0 {
+            // The rest of a burst keeps coming after the trigger is let go
+            self.burst_timer -= dt;
+            if self.burst_timer <= 0.0 {
+                self.burst_left -= 1;
+                self.burst_timer += burst_interval;
+                self.shoot(target, bullets);
+            }
         }
-
-        // Update bullets
-        for bullet in &mut self.bullets {
-            bullet.update();
-        }
-
-        Ok(())
     }
 
-    pub fn draw(&mut self, ctx: &mut Context) -> GameResult {
+    // The stats text is drawn right away, the sprite, health bar, laser and drones go through the renderer
+    pub fn draw(&self, ctx: &mut Context, renderer: &mut Renderer, aim: &Aim, alpha: f32) -> GameResult {
         self.draw_ui(ctx)?;
 
-        let mouse_pos = mouse::position(ctx);
-        let dx = mouse_pos.x - self.player_pos.x;
-        let dy = mouse_pos.y - self.player_pos.y;
-        let angle = dy.atan2(dx);
-    
-        graphics::draw(ctx, &self.player_image, DrawParam::default()
-            .dest([self.player_pos.x, self.player_pos.y])
+        let pos = interpolate(&self.prev_pos, &self.player_pos, alpha);
+        let angle = aim.angle(&pos);
+
+        if let Some(beam) = &self.beam {
+            renderer.line(pos, pos + beam.direction * beam.length, beam.width, beam.color)?;
+        }
+        let drone_color = self.weapon().def.color();
+        for drone in self.drone_positions(pos) {
+            renderer.circle(drone, DRONE_RADIUS, drone_color)?;
+        }
+
+        // Blinks while invulnerable after a hit
+        let faded = self.invulnerable > 0.0 && (self.invulnerable / BLINK_PERIOD) as i32 % 2 == 0;
+        let alpha_channel = if faded { 0.25 } else { 1.0 };
+        renderer.player(DrawParam::default()
+            .dest([pos.x, pos.y])
             .rotation(angle)
             .offset([0.5, 0.5])
-        )?;
+            .color(Color::new(1.0, 1.0, 1.0, alpha_channel))
+        );
     
         let health_bar_width = 50.0;
         let health_bar_height = 5.0;
-        let health_percentage = self.hp as f32 / 100.0;
+        let health_percentage = (self.hp as f32 / self.max_hp() as f32).clamp(0.0, 1.0);
         let health_bar = graphics::Rect::new(
-            self.player_pos.x - 25.0,
-            self.player_pos.y - 50.0, // Above the player
+            pos.x - 25.0,
+            pos.y - 50.0, // Above the player
             health_bar_width * health_percentage,
             health_bar_height,
         );
-        let health_mesh = Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            health_bar,
-            Color::from_rgb(255, 0, 0),
-        )?;
-        graphics::draw(ctx, &health_mesh, DrawParam::default())?;
-    
-        // Draw bullets
-        for bullet in &self.bullets {
-            bullet.draw(ctx)?;
-        }
-        graphics::present(ctx)?;
-        Ok(())
+        renderer.rect(health_bar, Color::from_rgb(255, 0, 0))
     }
 
     pub fn is_dead(&self) -> bool {
         self.hp <= 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Effect;
+    use crate::world::FIXED_DT;
+
+    #[test]
+    fn drones_fire_along_the_stick() {
+        let weapons: WeaponLibrary = toml::from_str(r#"
+            [[weapons]]
+            id = "drones"
+            name = "DRONES"
+            kind = "drones"
+            projectiles = 4
+            orbit_radius = 70
+        "#).unwrap();
+        let mut player = Player::new([40.0, 40.0], &weapons).unwrap();
+        let mut bullets = BulletPool::with_capacity(16);
+        let input = InputState { fire: true, aim: Aim::Towards(na::Vector2::y()), ..InputState::default() };
+        while bullets.len() == 0 {
+            player.update(&input, FIXED_DT, &mut bullets);
+        }
+
+        assert_eq!(bullets.len(), 4);
+        for bullet in bullets.values() {
+            assert!((bullet.heading - std::f32::consts::FRAC_PI_2).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn power_ups_wear_off_from_the_player_and_weapons() {
+        let weapons: WeaponLibrary = toml::from_str(r#"
+            [[weapons]]
+            id = "pistol"
+            name = "PISTOL"
+            kind = "gun"
+            damage = 4
+        "#).unwrap();
+        let mut player = Player::new([40.0, 40.0], &weapons).unwrap();
+        let power_up = Source::PowerUp(0);
+        player.add_modifier(Modifier { stat: Stat::MaxHp, effect: Effect::Add(50.0), source: power_up }, None);
+        // Weapon factors may go below 1, only the final damage is floored
+        player.add_modifier(Modifier { stat: Stat::Damage, effect: Effect::Multiply(0.5), source: power_up }, Some(0));
+        assert_eq!((player.max_hp(), player.hp), (150, 150));
+        assert_eq!(player.shot_damage(), 2);
+
+        assert_eq!(player.remove_source(power_up), 2);
+        assert_eq!((player.max_hp(), player.hp), (100, 100));
+        assert_eq!(player.shot_damage(), 4);
+    }
+}
@@ -0,0 +1,30 @@
+use ggez::graphics::Rect;
+use nalgebra as na;
+
+// Size of the world in world units. Everything in the simulation uses these
+// coordinates, the camera scales them to whatever the window size is.
+pub const WIDTH: f32 = 1500.0;
+pub const HEIGHT: f32 = 1000.0;
+
+// How far past the edge bullets fly before they are removed
+pub const BULLET_MARGIN: f32 = 100.0;
+
+// How far past the edge the broadphase grids reach
+pub const GRID_MARGIN: f32 = 200.0;
+
+pub fn rect() -> Rect {
+    Rect::new(0.0, 0.0, WIDTH, HEIGHT)
+}
+
+pub fn center() -> na::Point2<f32> {
+    na::Point2::new(WIDTH / 2.0, HEIGHT / 2.0)
+}
+
+// Whether pos is inside the playfield grown by margin on every side
+pub fn contains(pos: &na::Point2<f32>, margin: f32) -> bool {
+    pos.x >= -margin && pos.x <= WIDTH + margin && pos.y >= -margin && pos.y <= HEIGHT + margin
+}
+
+pub fn clamp(pos: &na::Point2<f32>) -> na::Point2<f32> {
+    na::Point2::new(pos.x.clamp(0.0, WIDTH), pos.y.clamp(0.0, HEIGHT))
+}
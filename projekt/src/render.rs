@@ -0,0 +1,88 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::graphics::spritebatch::SpriteBatch;
+use nalgebra as na;
+
+use crate::sprites::Sprites;
+
+// Collects a frame's sprites and shapes and draws them in a fixed number of
+// draw calls: one SpriteBatch per enemy archetype, one for the player and a
+// single mesh holding every bullet, health bar, pickup, shockwave and laser.
+// Entities queue themselves while drawing and flush sends everything to the GPU.
+pub struct Renderer {
+    enemies: Vec<SpriteBatch>, // Same order as the enemy catalogue
+    player: SpriteBatch,
+    shapes: MeshBuilder,
+    shape_count: usize,
+    pub draw_calls: usize, // Made by the last flush
+}
+
+impl Renderer {
+    pub fn new(sprites: &Sprites) -> Renderer {
+        Renderer {
+            enemies: sprites.enemies.iter().map(|image| SpriteBatch::new(image.clone())).collect(),
+            player: SpriteBatch::new(sprites.player.clone()),
+            shapes: MeshBuilder::new(),
+            shape_count: 0,
+            draw_calls: 0,
+        }
+    }
+
+    pub fn enemy(&mut self, archetype: usize, param: DrawParam) {
+        self.enemies[archetype].add(param);
+    }
+
+    pub fn player(&mut self, param: DrawParam) {
+        self.player.add(param);
+    }
+
+    // Filled rectangle drawn above all sprites
+    pub fn rect(&mut self, rect: Rect, color: Color) -> GameResult {
+        if rect.w > 0.0 && rect.h > 0.0 {
+            self.shapes.rectangle(DrawMode::fill(), rect, color)?;
+            self.shape_count += 1;
+        }
+        Ok(())
+    }
+
+    pub fn circle(&mut self, center: na::Point2<f32>, radius: f32, color: Color) -> GameResult {
+        self.shapes.circle(DrawMode::fill(), [center.x, center.y], radius, 1.0, color)?;
+        self.shape_count += 1;
+        Ok(())
+    }
+
+    // Outline of a circle, `width` pixels thick
+    pub fn ring(&mut self, center: na::Point2<f32>, radius: f32, width: f32, color: Color) -> GameResult {
+        self.shapes.circle(DrawMode::stroke(width), [center.x, center.y], radius, 1.0, color)?;
+        self.shape_count += 1;
+        Ok(())
+    }
+
+    pub fn line(&mut self, from: na::Point2<f32>, to: na::Point2<f32>, width: f32, color: Color) -> GameResult {
+        if from != to {
+            self.shapes.line(&[[from.x, from.y], [to.x, to.y]], width, color)?;
+            self.shape_count += 1;
+        }
+        Ok(())
+    }
+
+    // Draws everything queued since the last flush and starts a new frame
+    pub fn flush(&mut self, ctx: &mut Context) -> GameResult {
+        self.draw_calls = 0;
+        for batch in self.enemies.iter_mut().chain(std::iter::once(&mut self.player)) {
+            graphics::draw(ctx, batch, DrawParam::default())?;
+            batch.clear();
+            self.draw_calls += 1;
+        }
+
+        // An empty MeshBuilder can't be built
+        if self.shape_count > 0 {
+            let mesh = self.shapes.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+            self.draw_calls += 1;
+        }
+        self.shapes = MeshBuilder::new();
+        self.shape_count = 0;
+        Ok(())
+    }
+}
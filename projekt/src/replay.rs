@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use nalgebra as na;
+
+use crate::input::{Aim, InputState, WeaponSwitch};
+
+const MAGIC: &[u8; 4] = b"OTRP";
+const FORMAT_VERSION: u8 = 5;
+// Size of one recorded frame, laid out in Replay::save
+const FRAME_BYTES: usize = 4 + 2 + 1 + 1 + 4 * 4;
+
+// A frame has one byte for the purchase and one for the weapon switch, the
+// loaders refuse more upgrades and weapons than those can tell apart
+pub const MAX_UPGRADES: usize = u8::MAX as usize - 1; // 0 is no purchase
+pub const MAX_WEAPONS: usize = u8::MAX as usize - 2; // 0 to 2 are no switch, next and previous
+
+// One recorded simulation step
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayFrame {
+    pub dt: f32,
+    pub input: InputState,
+}
+
+// Seed, game mode and every frame's input, enough to re-run a game exactly
+pub struct Replay {
+    pub version: String,
+    pub seed: u64,
+    pub endless: bool,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, endless: bool) -> Replay {
+        Replay {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            endless,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, dt: f32, input: &InputState) {
+        self.frames.push(ReplayFrame { dt, input: *input });
+    }
+
+    pub fn is_current_version(&self) -> bool {
+        self.version == env!("CARGO_PKG_VERSION")
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        out.write_all(&(self.version.len() as u16).to_le_bytes())?;
+        out.write_all(self.version.as_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&[self.endless as u8])?;
+        out.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+
+        // FRAME_BYTES per frame: dt, button bits, bought upgrade + 1 (0 for none), weapon switch, aim x, aim y, stick x, stick y
+        for frame in &self.frames {
+            let input = &frame.input;
+            let aim = match input.aim {
+                Aim::At(target) => target.coords,
+                Aim::Towards(direction) => direction,
+            };
+            out.write_all(&frame.dt.to_le_bytes())?;
+            out.write_all(&pack_buttons(input).to_le_bytes())?;
+            out.write_all(&[input.buy.map_or(0, |upgrade| upgrade as u8 + 1)])?;
+            out.write_all(&[pack_weapon_switch(input.weapon)])?;
+            for value in [aim.x, aim.y, input.stick.x, input.stick.y] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        out.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len() as usize;
+        let mut data = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        data.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a replay file"));
+        }
+        let format = read_bytes::<1>(&mut data)?[0];
+        if format != FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported replay format {}", format)));
+        }
+
+        let version_len = u16::from_le_bytes(read_bytes(&mut data)?) as usize;
+        let mut version = vec![0; version_len];
+        data.read_exact(&mut version)?;
+        let version = String::from_utf8(version).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let seed = u64::from_le_bytes(read_bytes(&mut data)?);
+        let endless = read_bytes::<1>(&mut data)?[0] != 0;
+        let frame_count = u32::from_le_bytes(read_bytes(&mut data)?) as usize;
+
+        // The count comes from the file, a corrupt one must not reserve more than the file can hold
+        let header_len = MAGIC.len() + 1 + 2 + version_len + 8 + 1 + 4;
+        let mut frames = Vec::with_capacity(frame_count.min(file_len.saturating_sub(header_len) / FRAME_BYTES));
+        for _ in 0..frame_count {
+            let dt = f32::from_le_bytes(read_bytes(&mut data)?);
+            let buttons = u16::from_le_bytes(read_bytes(&mut data)?);
+            let buy = read_bytes::<1>(&mut data)?[0];
+            let weapon = read_bytes::<1>(&mut data)?[0];
+            let mut values = [0.0; 4];
+            for value in &mut values {
+                *value = f32::from_le_bytes(read_bytes(&mut data)?);
+            }
+            let [aim_x, aim_y, stick_x, stick_y] = values;
+            let mut input = unpack_buttons(buttons);
+            input.aim = if buttons & AIM_DIRECTION_BIT != 0 {
+                Aim::Towards(na::Vector2::new(aim_x, aim_y))
+            } else {
+                Aim::At(na::Point2::new(aim_x, aim_y))
+            };
+            input.stick = na::Vector2::new(stick_x, stick_y);
+            input.buy = buy.checked_sub(1).map(usize::from);
+            input.weapon = unpack_weapon_switch(weapon);
+            frames.push(ReplayFrame { dt, input });
+        }
+
+        Ok(Replay { version, seed, endless, frames })
+    }
+}
+
+fn read_bytes<const N: usize>(data: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    data.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Set when the frame's aim is a stick direction instead of a point
+const AIM_DIRECTION_BIT: u16 = 1 << 5;
+
+fn pack_buttons(input: &InputState) -> u16 {
+    let buttons = [input.up, input.down, input.left, input.right, input.fire,
+        matches!(input.aim, Aim::Towards(_)), input.undo, input.bomb];
+    buttons.iter().enumerate().fold(0, |bits, (i, &pressed)| bits | ((pressed as u16) << i))
+}
+
+// 0 for none, 1 next, 2 previous, then the slot + 3
+fn pack_weapon_switch(switch: Option<WeaponSwitch>) -> u8 {
+    match switch {
+        None => 0,
+        Some(WeaponSwitch::Next) => 1,
+        Some(WeaponSwitch::Previous) => 2,
+        Some(WeaponSwitch::Slot(slot)) => slot as u8 + 3,
+    }
+}
+
+fn unpack_weapon_switch(byte: u8) -> Option<WeaponSwitch> {
+    match byte {
+        0 => None,
+        1 => Some(WeaponSwitch::Next),
+        2 => Some(WeaponSwitch::Previous),
+        slot => Some(WeaponSwitch::Slot(usize::from(slot - 3))),
+    }
+}
+
+// Aim, stick, purchase and weapon switch are filled in from the rest of the frame
+fn unpack_buttons(bits: u16) -> InputState {
+    let pressed = |i: u16| bits & (1 << i) != 0;
+    InputState {
+        up: pressed(0),
+        down: pressed(1),
+        left: pressed(2),
+        right: pressed(3),
+        fire: pressed(4),
+        undo: pressed(6),
+        bomb: pressed(7),
+        ..InputState::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file in the temp directory, removed again when the test is done with it
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            TempFile(std::env::temp_dir().join(format!("{}-{}.replay", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn recording() -> Replay {
+        let mut replay = Replay::new(42, true);
+        replay.record(1.0 / 60.0, &InputState { up: true, fire: true, aim: Aim::At(na::Point2::new(10.5, -3.0)), ..InputState::default() });
+        replay.record(0.0, &InputState { buy: Some(MAX_UPGRADES - 1), undo: true, ..InputState::default() });
+        replay.record(1.0 / 60.0, &InputState {
+            down: true,
+            left: true,
+            right: true,
+            bomb: true,
+            stick: na::Vector2::new(0.5, -0.25),
+            weapon: Some(WeaponSwitch::Slot(MAX_WEAPONS - 1)),
+            aim: Aim::Towards(na::Vector2::new(0.0, 1.0)),
+            ..InputState::default()
+        });
+        replay.record(1.0 / 60.0, &InputState { weapon: Some(WeaponSwitch::Next), ..InputState::default() });
+        replay.record(1.0 / 60.0, &InputState { weapon: Some(WeaponSwitch::Previous), ..InputState::default() });
+        replay
+    }
+
+    #[test]
+    fn saved_replays_load_back_the_same() {
+        let file = TempFile::new("round-trip");
+        let replay = recording();
+        replay.save(&file.0).unwrap();
+
+        let loaded = Replay::load(&file.0).unwrap();
+        assert_eq!((loaded.version.as_str(), loaded.seed, loaded.endless), (env!("CARGO_PKG_VERSION"), 42, true));
+        assert!(loaded.is_current_version());
+        assert_eq!(loaded.frames.len(), replay.frames.len());
+        for (loaded, recorded) in loaded.frames.iter().zip(&replay.frames) {
+            assert_eq!(loaded.dt, recorded.dt);
+            assert_eq!(loaded.input, recorded.input);
+        }
+    }
+
+    #[test]
+    fn other_formats_and_cut_files_are_refused() {
+        let file = TempFile::new("format");
+        recording().save(&file.0).unwrap();
+        let mut bytes = std::fs::read(&file.0).unwrap();
+
+        bytes[MAGIC.len()] = FORMAT_VERSION - 1;
+        std::fs::write(&file.0, &bytes).unwrap();
+        let error = Replay::load(&file.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("unsupported replay format"), "{}", error);
+
+        bytes[MAGIC.len()] = FORMAT_VERSION;
+        bytes.truncate(bytes.len() - FRAME_BYTES / 2);
+        std::fs::write(&file.0, &bytes).unwrap();
+        assert_eq!(Replay::load(&file.0).err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn other_game_versions_are_told_apart() {
+        let file = TempFile::new("version");
+        let mut replay = recording();
+        replay.version = "0.0.1-old".to_string();
+        replay.save(&file.0).unwrap();
+
+        let loaded = Replay::load(&file.0).unwrap();
+        assert_eq!(loaded.version, "0.0.1-old");
+        assert!(!loaded.is_current_version());
+    }
+}
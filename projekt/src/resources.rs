@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ggez::{GameError, GameResult};
+
+// Reads a file from the resources folder without needing a ggez Context,
+// looking next to the working directory first and then in the crate folder.
+// Accepts ggez style names with a leading slash too.
+pub fn read_resource_bytes(name: &str) -> GameResult<Vec<u8>> {
+    let name = name.trim_start_matches('/');
+    let candidates = [
+        PathBuf::from("resources").join(name),
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("resources").join(name),
+    ];
+
+    for path in &candidates {
+        if let Ok(bytes) = fs::read(path) {
+            return Ok(bytes);
+        }
+    }
+    Err(GameError::ResourceLoadError(format!("could not find resources/{}", name)))
+}
+
+pub fn read_resource(name: &str) -> GameResult<String> {
+    let bytes = read_resource_bytes(name)?;
+    String::from_utf8(bytes).map_err(|e| GameError::ResourceLoadError(format!("resources/{}: {}", name, e)))
+}
+
+// Width and height of a PNG, read from its header so the simulation knows sprite sizes without a window
+pub fn image_size(name: &str) -> GameResult<[f32; 2]> {
+    let bytes = read_resource_bytes(name)?;
+    if bytes.len() < 24 || &bytes[..8] != b"\x89PNG\r\n\x1a\n" {
+        return Err(GameError::ResourceLoadError(format!("resources/{} is not a PNG", name.trim_start_matches('/'))));
+    }
+    let width = u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]);
+    let height = u32::from_be_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]);
+    Ok([width as f32, height as f32])
+}
+
+// Reads and parses a TOML file from the resources folder
+pub fn load_toml<T: serde::de::DeserializeOwned>(name: &str) -> GameResult<T> {
+    let text = read_resource(name)?;
+    toml::from_str(&text).map_err(|e| GameError::ResourceLoadError(format!("resources/{}: {}", name, e)))
+}
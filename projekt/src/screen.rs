@@ -0,0 +1,66 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, Text};
+
+use crate::playfield;
+use crate::bindings::Action;
+
+// What the window shows. Game keeps these on a stack: the top one gets the
+// input, and overlays (Paused, Shop, Settings, Controls) are drawn over the screen at
+// the bottom of the stack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Screen {
+    Menu,
+    Playing,
+    Paused,
+    Shop,
+    Settings,
+    Controls,
+    GameOver,
+}
+
+impl Screen {
+    // Choices listed on the screen, picked with up/down and enter
+    pub fn options(self) -> Vec<&'static str> {
+        match self {
+            Screen::Paused => vec!["RESUME", "SHOP", "SETTINGS", "RESTART", "QUIT TO MENU"],
+            Screen::Settings => vec!["HITBOXES", "DEBUG STATS", "FULLSCREEN", "MOVE DEADZONE", "AIM DEADZONE", "CONTROLS", "BACK"],
+            Screen::Shop => vec!["UNDO LAST PURCHASE", "BACK"], // After the upgrades, which come from the shop
+            Screen::Controls => Action::ALL.iter().map(|action| action.label()).chain(["RESET DEFAULTS", "BACK"]).collect(),
+            Screen::GameOver => vec!["PLAY AGAIN", "QUIT TO MENU"],
+            Screen::Menu => vec!["START", "SETTINGS", "QUIT"],
+            Screen::Playing => Vec::new(),
+        }
+    }
+}
+
+// Darkens whatever is under an overlay screen
+pub fn draw_dim(ctx: &mut Context) -> GameResult {
+    let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), playfield::rect(), Color::new(0.0, 0.0, 0.0, 0.6))?;
+    graphics::draw(ctx, &mesh, DrawParam::default())
+}
+
+// Title and a vertical list of options with the selected one highlighted.
+// `values` are shown after the options that have one, like ON / OFF.
+// Long lists get smaller lines so they still fit under the title.
+pub fn draw_options(ctx: &mut Context, title: &str, options: &[&str], values: &[Option<String>], selected: usize) -> GameResult {
+    let title = Text::new((title, graphics::Font::default(), 80.0));
+    let title_w = title.width(ctx);
+    graphics::draw(ctx, &title, DrawParam::default().dest([(playfield::WIDTH - title_w) / 2.0, 250.0]))?;
+
+    let spacing = (560.0 / options.len() as f32).min(60.0);
+    for (i, option) in options.iter().enumerate() {
+        let line = match values.get(i) {
+            Some(Some(value)) => format!("{}: {}", option, value),
+            _ => option.to_string(),
+        };
+        let line = if i == selected { format!("> {} <", line) } else { line };
+        let color = if i == selected { Color::from_rgb(255, 220, 0) } else { Color::WHITE };
+
+        let text = Text::new((line, graphics::Font::default(), spacing * 0.75));
+        let text_w = text.width(ctx);
+        graphics::draw(ctx, &text, DrawParam::default()
+            .dest([(playfield::WIDTH - text_w) / 2.0, 380.0 + i as f32 * spacing])
+            .color(color))?;
+    }
+    Ok(())
+}
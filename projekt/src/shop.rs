@@ -1,91 +1,601 @@
+use std::collections::BTreeMap;
+
 use crate::player::Player;
-use ggez::{Context, GameResult};
-use ggez::graphics::{self, DrawParam};
+use crate::replay::MAX_UPGRADES;
+use crate::resources::load_toml;
+use crate::bomb::MAX_BOMBS;
+use crate::stats::{Effect, Modifier, Source, Stat, Stats};
+use crate::weapon::{WeaponLibrary, WEAPON_STATS};
+use ggez::{GameError, GameResult};
+use serde::Deserialize;
 
 
-pub struct Shop {
-    pub health_upgrade_cost: i32,
-    pub damage_upgrade_cost: i32,
-    pub speed_upgrade_cost: i32,
-    pub fire_rate_cost: i32,
+// Price of the next level: (base + per_level * level) * growth ^ level
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct CostCurve {
+    pub base: f32,
+    #[serde(default)]
+    pub per_level: f32,
+    #[serde(default = "default_growth")]
+    pub growth: f32,
+}
+
+impl CostCurve {
+    pub fn at(&self, level: u32) -> i32 {
+        ((self.base + self.per_level * level as f32) * self.growth.powi(level as i32)).round() as i32
+    }
+}
+
+fn default_growth() -> f32 {
+    1.0
+}
+
+// Consumables the shop sells besides stat levels
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Item {
+    Bomb, // One bomb charge
+}
+
+// What every level of an upgrade gives
+#[derive(Clone, Copy, Debug)]
+pub enum Reward {
+    Stat(Stat, Effect),
+    Item(Item),
+}
+
+// One upgrade as written in resources/upgrades.toml
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "UpgradeFields")]
+pub struct UpgradeDef {
+    pub id: String,
+    pub name: String,
+    pub reward: Reward,
+    pub cost: CostCurve,
+    pub max_level: Option<u32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub requires: BTreeMap<String, u32>,
+    pub weapon: Option<String>, // Upgrades only this weapon's stats
+    // `weapon` as an index in weapons.toml, filled in when loading
+    pub weapon_index: Option<usize>,
+}
 
-    pub health_lvl: i32,
-    pub damage_lvl: i32,
-    pub speed_lvl: i32,
-    pub fire_rate_lvl: i32,
+// The file has either `stat` and `effect` or `item`
+#[derive(Deserialize)]
+struct UpgradeFields {
+    id: String,
+    name: String,
+    stat: Option<Stat>,
+    effect: Option<Effect>,
+    item: Option<Item>,
+    cost: CostCurve,
+    max_level: Option<u32>,
+    min: Option<f32>,
+    max: Option<f32>,
+    #[serde(default)]
+    requires: BTreeMap<String, u32>,
+    weapon: Option<String>,
+}
+
+impl TryFrom<UpgradeFields> for UpgradeDef {
+    type Error = String;
+
+    fn try_from(fields: UpgradeFields) -> Result<UpgradeDef, String> {
+        let reward = match (fields.stat, fields.effect, fields.item) {
+            (Some(stat), Some(effect), None) => Reward::Stat(stat, effect),
+            (None, None, Some(item)) => Reward::Item(item),
+            _ => return Err(format!("{} needs either a stat and an effect or an item", fields.id)),
+        };
+        Ok(UpgradeDef {
+            id: fields.id,
+            name: fields.name,
+            reward,
+            cost: fields.cost,
+            max_level: fields.max_level,
+            min: fields.min,
+            max: fields.max,
+            requires: fields.requires,
+            weapon: fields.weapon,
+            weapon_index: None,
+        })
+    }
+}
+
+impl UpgradeDef {
+    // Shown as the next level's effect, like "+10 HP" or "x1.15 BULLET SPEED"
+    pub fn effect_text(&self) -> String {
+        match self.reward {
+            Reward::Stat(stat, Effect::Add(amount)) => format!("{:+} {}", amount, stat.label()),
+            Reward::Stat(stat, Effect::Multiply(factor)) => format!("x{} {}", factor, stat.label()),
+            Reward::Item(Item::Bomb) => "+1 BOMB".to_string(),
+        }
+    }
+}
+
+// Contents of resources/upgrades.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct UpgradeTree {
+    pub upgrades: Vec<UpgradeDef>,
+}
+
+impl UpgradeTree {
+    pub fn load(weapons: &WeaponLibrary) -> GameResult<UpgradeTree> {
+        let tree: UpgradeTree = load_toml("upgrades.toml")?;
+        tree.resolve(weapons)
+    }
+
+    // Checks the upgrades and turns their weapon ids into indices
+    fn resolve(self, weapons: &WeaponLibrary) -> GameResult<UpgradeTree> {
+        let mut tree = self;
+        let error = |message: String| Err(GameError::ResourceLoadError(format!("resources/upgrades.toml: {}", message)));
+
+        if tree.upgrades.len() > MAX_UPGRADES {
+            return error(format!("has more than {} upgrades", MAX_UPGRADES));
+        }
+        for (i, def) in tree.upgrades.iter().enumerate() {
+            if tree.upgrades[..i].iter().any(|other| other.id == def.id) {
+                return error(format!("{} is listed twice", def.id));
+            }
+            if let Some(missing) = def.requires.keys().find(|id| !tree.upgrades.iter().any(|other| &other.id == *id)) {
+                return error(format!("{} requires unknown upgrade {}", def.id, missing));
+            }
+            if def.cost.base <= 0.0 || def.cost.growth <= 0.0 || def.cost.per_level < 0.0 {
+                return error(format!("{} needs a positive cost base and growth and no negative per_level", def.id));
+            }
+            if let Reward::Stat(_, Effect::Multiply(factor)) = def.reward {
+                if factor <= 0.0 {
+                    return error(format!("{} needs a positive multiply", def.id));
+                }
+            }
+        }
+
+        if let Some(id) = tree.requirement_cycle() {
+            return error(format!("{} requires itself through its requirements, so it can never be bought", id));
+        }
+
+        for def in &mut tree.upgrades {
+            let weapon = match &def.weapon {
+                Some(weapon) => weapon,
+                None => continue,
+            };
+            match def.reward {
+                Reward::Stat(stat, _) if WEAPON_STATS.contains(&stat) => {}
+                _ => return error(format!("{} upgrades a weapon, which only has damage, fire_rate, projectiles, spread and pierce", def.id)),
+            }
+            match weapons.find(weapon) {
+                Some(i) => def.weapon_index = Some(i),
+                None => return error(format!("{} upgrades unknown weapon {}", def.id, weapon)),
+            }
+        }
+        Ok(tree)
+    }
+
+    // An upgrade on a loop of requirements, found by walking them depth first
+    fn requirement_cycle(&self) -> Option<&str> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Visit {
+            New,
+            OnPath,
+            Done,
+        }
+        let index = |id: &String| self.upgrades.iter().position(|def| &def.id == id);
+        let mut visits = vec![Visit::New; self.upgrades.len()];
+        for start in 0..self.upgrades.len() {
+            if visits[start] != Visit::New {
+                continue;
+            }
+            // Each entry is an upgrade and how many of its requirements were walked
+            let mut path = vec![(start, 0)];
+            visits[start] = Visit::OnPath;
+            while let Some((upgrade, next)) = path.last_mut() {
+                let required = self.upgrades[*upgrade].requires.keys().nth(*next).and_then(index);
+                *next += 1;
+                match required {
+                    Some(required) if visits[required] == Visit::OnPath => return Some(&self.upgrades[required].id),
+                    Some(required) if visits[required] == Visit::New => {
+                        visits[required] = Visit::OnPath;
+                        path.push((required, 0));
+                    }
+                    Some(_) => {}
+                    None if *next > self.upgrades[*upgrade].requires.len() => {
+                        visits[*upgrade] = Visit::Done;
+                        path.pop();
+                    }
+                    None => {}
+                }
+            }
+        }
+        None
+    }
+}
+
+// Why an upgrade can't be bought right now
+#[derive(Clone, Debug, PartialEq)]
+pub enum Blocked {
+    SoldOut,
+    Requires(String, u32), // Name and level of the missing upgrade
+    TooExpensive,
+}
+
+// A purchase that can still be refunded
+#[derive(Clone, Copy, Debug)]
+struct Purchase {
+    upgrade: usize,
+    cost: i32,
+}
+
+pub struct Shop {
+    pub upgrades: Vec<UpgradeDef>,
+    levels: Vec<u32>, // Times each upgrade was bought
+    last_purchase: Option<Purchase>,
 }
 
 impl Shop {
-    pub fn new() -> GameResult<Shop> {
-        let s = Shop {
-            health_upgrade_cost: 50,
-            damage_upgrade_cost: 500,
-            speed_upgrade_cost: 200,
-            fire_rate_cost: 400,
-
-            health_lvl: 1,
-            damage_lvl: 1,
-            speed_lvl: 1,
-            fire_rate_lvl: 1,
+    pub fn new(tree: UpgradeTree) -> Shop {
+        Shop {
+            levels: vec![0; tree.upgrades.len()],
+            upgrades: tree.upgrades,
+            last_purchase: None,
+        }
+    }
+
+    // An upgrade's min and max hold for its stat whatever else modifies it
+    pub fn limit_stats(&self, player: &mut Player) {
+        for def in &self.upgrades {
+            if let Reward::Stat(stat, _) = def.reward {
+                let stats = match def.weapon_index {
+                    Some(weapon) => &mut player.weapons[weapon].stats,
+                    None => &mut player.stats,
+                };
+                stats.limit(stat, def.min, def.max);
+            }
+        }
+    }
+
+    // The player's stats, or the weapon's for a weapon upgrade
+    fn stats_of<'a>(&self, upgrade: usize, player: &'a Player) -> &'a Stats {
+        match self.upgrades[upgrade].weapon_index {
+            Some(weapon) => &player.weapons[weapon].stats,
+            None => &player.stats,
+        }
+    }
+
+    // What one level of a stat upgrade adds to the player
+    fn modifier(&self, upgrade: usize) -> Option<Modifier> {
+        match self.upgrades[upgrade].reward {
+            Reward::Stat(stat, effect) => Some(Modifier { stat, effect, source: Source::Upgrade(upgrade) }),
+            Reward::Item(_) => None,
+        }
+    }
+
+    pub fn cost(&self, upgrade: usize) -> i32 {
+        self.upgrades[upgrade].cost.at(self.levels[upgrade])
+    }
+
+    fn level_of(&self, id: &str) -> u32 {
+        self.upgrades.iter().position(|def| def.id == id).map_or(0, |i| self.levels[i])
+    }
+
+    pub fn blocked(&self, upgrade: usize, player: &Player) -> Option<Blocked> {
+        let def = &self.upgrades[upgrade];
+        let maxed = def.max_level.is_some_and(|max| self.levels[upgrade] >= max);
+        // Sold out too once the stat sits on a limit and another level would change nothing,
+        // or the player can't carry more of the item
+        let capped = match (def.reward, self.modifier(upgrade)) {
+            (Reward::Stat(stat, _), Some(modifier)) => {
+                let stats = self.stats_of(upgrade, player);
+                stats.with(modifier) == stats.get(stat)
+            }
+            (Reward::Item(Item::Bomb), _) => player.bombs >= MAX_BOMBS,
+            _ => false,
         };
-        Ok(s)
+        if maxed || capped {
+            return Some(Blocked::SoldOut);
+        }
+        if let Some((id, &level)) = def.requires.iter().find(|(id, &level)| self.level_of(id) < level) {
+            let name = self.upgrades.iter().find(|other| &other.id == id).map_or(id.as_str(), |other| other.name.as_str());
+            return Some(Blocked::Requires(name.to_string(), level));
+        }
+        if player.coins < self.cost(upgrade) {
+            return Some(Blocked::TooExpensive);
+        }
+        None
     }
 
-    pub fn display(&self, ctx: &mut Context, player: &mut Player) -> GameResult {
-        let text = format!(
-            "Press NUM key to buy\n1. Health Upgrade: {} \n2. Damage Upgrade: {} \n3. Speed Upgrade: {} \n4. Fire Rate Upgrade: {} \nCoins: {}",
-            self.health_upgrade_cost, self.damage_upgrade_cost, self.speed_upgrade_cost, self.fire_rate_cost, player.coins
-        );
-        let display_text = graphics::Text::new((text, graphics::Font::default(), 30.0));
-        graphics::draw(ctx, &display_text, DrawParam::default())?;
-        Ok(())
+    // Shop screen line for an upgrade
+    pub fn describe(&self, upgrade: usize, player: &Player) -> String {
+        let def = &self.upgrades[upgrade];
+        let level = match def.max_level {
+            Some(max) => format!("LV {}/{}", self.levels[upgrade], max),
+            None => format!("LV {}", self.levels[upgrade]),
+        };
+        match self.blocked(upgrade, player) {
+            Some(Blocked::SoldOut) => format!("{}, SOLD OUT", level),
+            Some(Blocked::Requires(name, needed)) => format!("{}, NEEDS {} LV {}", level, name, needed),
+            Some(Blocked::TooExpensive) => format!("{}, NEXT {}, {} COINS (NOT ENOUGH)", level, def.effect_text(), self.cost(upgrade)),
+            None => format!("{}, NEXT {}, {} COINS", level, def.effect_text(), self.cost(upgrade)),
+        }
     }
 
-    pub fn try_buy_health_upgrade(&mut self, player: &mut Player) {
-        if player.coins >= self.health_upgrade_cost {
-            player.coins -= self.health_upgrade_cost;
-            player.hp += 10; // Add health
-            self.health_upgrade_cost = self.health_upgrade_cost / self.health_lvl * (self.health_lvl + 1); // Change cost
-            self.health_lvl += 1;
-            println!("Health upgraded! New HP: {}", player.hp);
-        } else {
-            println!("Not enough coins for health upgrade.");
+    pub fn last_purchase(&self) -> Option<&UpgradeDef> {
+        self.last_purchase.map(|purchase| &self.upgrades[purchase.upgrade])
+    }
+
+    pub fn try_buy(&mut self, upgrade: usize, player: &mut Player) -> bool {
+        if upgrade >= self.upgrades.len() {
+            return false;
         }
+        if self.blocked(upgrade, player).is_some() {
+            println!("Can't buy {}: {}", self.upgrades[upgrade].name, self.describe(upgrade, player));
+            return false;
+        }
+
+        let cost = self.cost(upgrade);
+        player.coins -= cost;
+        match self.upgrades[upgrade].reward {
+            Reward::Stat(..) => player.add_modifier(self.modifier(upgrade).unwrap(), self.upgrades[upgrade].weapon_index),
+            Reward::Item(Item::Bomb) => player.bombs += 1,
+        }
+        self.levels[upgrade] += 1;
+        self.last_purchase = Some(Purchase { upgrade, cost });
+        println!("{} upgraded to level {}!", self.upgrades[upgrade].name, self.levels[upgrade]);
+        true
     }
 
-    pub fn try_buy_damage_upgrade(&mut self, player: &mut Player) {
-        if player.coins >= self.damage_upgrade_cost {
-            player.coins -= self.damage_upgrade_cost;
-            player.damage += 1; // Upgrade damage
-            self.damage_upgrade_cost = self.damage_upgrade_cost / self.damage_lvl * (self.damage_lvl + 1); // Change cost
-            self.damage_lvl += 1;
-            println!("Damage upgraded! New Damage: {}", player.damage);
-        } else {
-            println!("Not enough coins for damage upgrade.");
+    // Gives back the coins of the last purchase and takes its level away
+    pub fn undo(&mut self, player: &mut Player) -> bool {
+        let purchase = match self.last_purchase.take() {
+            Some(purchase) => purchase,
+            None => return false,
+        };
+        player.coins += purchase.cost;
+        match self.upgrades[purchase.upgrade].reward {
+            Reward::Stat(..) => {
+                player.remove_modifier(Source::Upgrade(purchase.upgrade), self.upgrades[purchase.upgrade].weapon_index);
+            }
+            Reward::Item(Item::Bomb) => player.bombs = player.bombs.saturating_sub(1),
         }
+        self.levels[purchase.upgrade] -= 1;
+        println!("{} upgrade refunded.", self.upgrades[purchase.upgrade].name);
+        true
+    }
+
+    // Purchases can only be undone until the game goes on
+    pub fn close(&mut self) {
+        self.last_purchase = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resolves `upgrades` against a pistol and a laser, so nothing depends on resources/
+    fn load(upgrades: &str) -> GameResult<UpgradeTree> {
+        let weapons: WeaponLibrary = toml::from_str(r#"
+            [[weapons]]
+            id = "pistol"
+            name = "PISTOL"
+            kind = "gun"
+
+            [[weapons]]
+            id = "laser"
+            name = "LASER"
+            kind = "laser"
+            range = 500
+        "#).unwrap();
+        let tree: UpgradeTree = toml::from_str(upgrades).unwrap();
+        tree.resolve(&weapons)
     }
 
-    pub fn try_buy_speed_upgrade(&mut self, player: &mut Player) {
-        if player.coins >= self.speed_upgrade_cost {
-            player.coins -= self.speed_upgrade_cost;
-            player.speed += 1.0; // Upgrade speed
-            self.speed_upgrade_cost = self.speed_upgrade_cost / self.speed_lvl * (self.speed_lvl + 1); // Change cost
-            self.speed_lvl += 1;
-            println!("Speed upgraded! New Speed: {}", player.speed);
-        } else {
-            println!("Not enough coins for speed upgrade.");
+    fn load_error(upgrades: &str) -> String {
+        match load(upgrades) {
+            Err(GameError::ResourceLoadError(message)) => message,
+            Err(other) => panic!("wrong error: {:?}", other),
+            Ok(_) => panic!("loaded a broken upgrade tree"),
         }
     }
 
-    pub fn try_buy_fire_rate_upgrade(&mut self, player: &mut Player) {
-        if player.coins >= self.fire_rate_cost {
-            player.coins -= self.fire_rate_cost;
-            player.fire_rate -= 0.05; // Upgrade fire rate
-            self.fire_rate_cost = self.fire_rate_cost / self.fire_rate_lvl * (self.fire_rate_lvl + 1); // Change cost
-            self.fire_rate_lvl += 1;
-            println!("Fire rate upgraded! New Fire Rate: {}", player.fire_rate);
-        } else {
-            println!("Not enough coins for fire rate upgrade.");
+    #[test]
+    fn weapon_ids_become_indices() {
+        let tree = load(r#"
+            [[upgrades]]
+            id = "health"
+            name = "HEALTH"
+            stat = "max_hp"
+            effect = { add = 10 }
+            cost = { base = 50 }
+
+            [[upgrades]]
+            id = "laser_damage"
+            name = "LASER DAMAGE"
+            stat = "damage"
+            effect = { multiply = 1.2 }
+            cost = { base = 300 }
+            weapon = "laser"
+            requires = { health = 1 }
+        "#).unwrap();
+
+        assert_eq!(tree.upgrades[0].weapon_index, None);
+        assert_eq!(tree.upgrades[1].weapon_index, Some(1));
+    }
+
+    #[test]
+    fn unknown_requirement_is_refused() {
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "damage"
+            name = "DAMAGE"
+            stat = "damage"
+            effect = { add = 1 }
+            cost = { base = 500 }
+            requires = { helth = 2 }
+        "#);
+        assert_eq!(message, "resources/upgrades.toml: damage requires unknown upgrade helth");
+    }
+
+    #[test]
+    fn requirement_cycles_are_refused() {
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "health"
+            name = "HEALTH"
+            stat = "max_hp"
+            effect = { add = 10 }
+            cost = { base = 50 }
+
+            [[upgrades]]
+            id = "damage"
+            name = "DAMAGE"
+            stat = "damage"
+            effect = { add = 1 }
+            cost = { base = 500 }
+            requires = { health = 1, speed = 1 }
+
+            [[upgrades]]
+            id = "speed"
+            name = "SPEED"
+            stat = "speed"
+            effect = { add = 60 }
+            cost = { base = 200 }
+            requires = { damage = 2 }
+        "#);
+        assert!(message.contains("requires itself"), "{}", message);
+
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "health"
+            name = "HEALTH"
+            stat = "max_hp"
+            effect = { add = 10 }
+            cost = { base = 50 }
+            requires = { health = 1 }
+        "#);
+        assert_eq!(message, "resources/upgrades.toml: health requires itself through its requirements, so it can never be bought");
+    }
+
+    #[test]
+    fn shared_requirements_are_not_a_cycle() {
+        let tree = load(r#"
+            [[upgrades]]
+            id = "health"
+            name = "HEALTH"
+            stat = "max_hp"
+            effect = { add = 10 }
+            cost = { base = 50 }
+
+            [[upgrades]]
+            id = "damage"
+            name = "DAMAGE"
+            stat = "damage"
+            effect = { add = 1 }
+            cost = { base = 500 }
+            requires = { health = 1 }
+
+            [[upgrades]]
+            id = "speed"
+            name = "SPEED"
+            stat = "speed"
+            effect = { add = 60 }
+            cost = { base = 200 }
+            requires = { health = 2, damage = 1 }
+        "#);
+        assert!(tree.is_ok());
+    }
+
+    #[test]
+    fn unknown_weapon_is_refused() {
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "shotgun_spread"
+            name = "SHOTGUN SPREAD"
+            stat = "spread"
+            effect = { add = 5 }
+            cost = { base = 300 }
+            weapon = "shotgun"
+        "#);
+        assert_eq!(message, "resources/upgrades.toml: shotgun_spread upgrades unknown weapon shotgun");
+    }
+
+    #[test]
+    fn weapon_upgrades_only_take_weapon_stats() {
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "pistol_speed"
+            name = "PISTOL SPEED"
+            stat = "speed"
+            effect = { add = 60 }
+            cost = { base = 200 }
+            weapon = "pistol"
+        "#);
+        assert!(message.starts_with("resources/upgrades.toml: pistol_speed upgrades a weapon"), "{}", message);
+
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "pistol_bomb"
+            name = "PISTOL BOMB"
+            item = "bomb"
+            cost = { base = 200 }
+            weapon = "pistol"
+        "#);
+        assert!(message.starts_with("resources/upgrades.toml: pistol_bomb upgrades a weapon"), "{}", message);
+    }
+
+    #[test]
+    fn cost_curves_must_stay_positive() {
+        for cost in ["{ base = 0 }", "{ base = 100, growth = 0 }", "{ base = 100, per_level = -10 }"] {
+            let message = load_error(&format!(r#"
+                [[upgrades]]
+                id = "health"
+                name = "HEALTH"
+                stat = "max_hp"
+                effect = {{ add = 10 }}
+                cost = {}
+            "#, cost));
+            assert!(message.contains("positive cost"), "{}: {}", cost, message);
         }
     }
+
+    #[test]
+    fn cost_curve_grows_by_level_and_growth() {
+        let linear = CostCurve { base: 50.0, per_level: 50.0, growth: 1.0 };
+        assert_eq!(linear.at(0), 50);
+        assert_eq!(linear.at(3), 200);
+
+        let compound = CostCurve { base: 100.0, per_level: 10.0, growth: 1.5 };
+        assert_eq!(compound.at(0), 100);
+        assert_eq!(compound.at(1), 165);
+        assert_eq!(compound.at(2), 270);
+    }
+
+    #[test]
+    fn duplicates_and_bad_factors_are_refused() {
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "health"
+            name = "HEALTH"
+            stat = "max_hp"
+            effect = { add = 10 }
+            cost = { base = 50 }
+
+            [[upgrades]]
+            id = "health"
+            name = "MORE HEALTH"
+            stat = "max_hp"
+            effect = { add = 20 }
+            cost = { base = 80 }
+        "#);
+        assert_eq!(message, "resources/upgrades.toml: health is listed twice");
+
+        let message = load_error(r#"
+            [[upgrades]]
+            id = "damage"
+            name = "DAMAGE"
+            stat = "damage"
+            effect = { multiply = 0 }
+            cost = { base = 500 }
+        "#);
+        assert_eq!(message, "resources/upgrades.toml: damage needs a positive multiply");
+    }
 }
@@ -0,0 +1,164 @@
+use ggez::graphics::Rect;
+
+// Marks the end of a cell's list
+const END: u32 = u32::MAX;
+
+// Uniform grid broadphase. Entities are inserted by their bounding rect every
+// tick and queries return only the ids sharing a cell with the query rect, so
+// the exact collision checks run on a handful of candidates instead of everyone.
+//
+// Every cell is a linked list through one shared entries buffer, so however
+// the entities bunch up the grid allocates nothing while the entries fit in
+// the capacity it was made with.
+pub struct SpatialGrid<T> {
+    min_x: f32,
+    min_y: f32,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+    heads: Vec<u32>, // First entry of every cell, END when it is empty
+    entries: Vec<(T, u32)>, // An id and the next entry of the same cell
+}
+
+impl<T: Copy + Ord> SpatialGrid<T> {
+    // Covers min..max; anything outside is kept in the border cells. `capacity`
+    // is how many cells the entities of a tick cover together.
+    pub fn new(min_x: f32, min_y: f32, max_x: f32, max_y: f32, cell_size: f32, capacity: usize) -> SpatialGrid<T> {
+        let cols = ((max_x - min_x) / cell_size).ceil().max(1.0) as usize;
+        let rows = ((max_y - min_y) / cell_size).ceil().max(1.0) as usize;
+        SpatialGrid {
+            min_x,
+            min_y,
+            cell_size,
+            cols,
+            rows,
+            heads: vec![END; cols * rows],
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    // Empties the grid but keeps the memory for the next tick
+    pub fn clear(&mut self) {
+        self.heads.fill(END);
+        self.entries.clear();
+    }
+
+    fn cell_range(&self, rect: &Rect) -> (usize, usize, usize, usize) {
+        let col = |x: f32| (((x - self.min_x) / self.cell_size).floor().max(0.0) as usize).min(self.cols - 1);
+        let row = |y: f32| (((y - self.min_y) / self.cell_size).floor().max(0.0) as usize).min(self.rows - 1);
+        (col(rect.x), col(rect.x + rect.w), row(rect.y), row(rect.y + rect.h))
+    }
+
+    pub fn insert(&mut self, id: T, rect: &Rect) {
+        let (col_start, col_end, row_start, row_end) = self.cell_range(rect);
+        for row in row_start..=row_end {
+            for col in col_start..=col_end {
+                let cell = row * self.cols + col;
+                self.entries.push((id, self.heads[cell]));
+                self.heads[cell] = self.entries.len() as u32 - 1;
+            }
+        }
+    }
+
+    // Fills `out` with the sorted, unique ids that may overlap `rect`
+    pub fn query(&self, rect: &Rect, out: &mut Vec<T>) {
+        out.clear();
+        let (col_start, col_end, row_start, row_end) = self.cell_range(rect);
+        for row in row_start..=row_end {
+            for col in col_start..=col_end {
+                let mut entry = self.heads[row * self.cols + col];
+                while entry != END {
+                    let (id, next) = self.entries[entry as usize];
+                    out.push(id);
+                    entry = next;
+                }
+            }
+        }
+        out.sort_unstable();
+        out.dedup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::world::GameRng;
+
+    const CELL: f32 = 100.0;
+
+    // 10 x 10 cells from 0 to 1000
+    fn grid() -> SpatialGrid<usize> {
+        SpatialGrid::new(0.0, 0.0, 1000.0, 1000.0, CELL, 16)
+    }
+
+    fn touches(a: &Rect, b: &Rect) -> bool {
+        a.x <= b.x + b.w && b.x <= a.x + a.w && a.y <= b.y + b.h && b.y <= a.y + a.h
+    }
+
+    fn shares_cell(a: &Rect, b: &Rect) -> bool {
+        let cell = |v: f32| (v / CELL).floor().clamp(0.0, 9.0);
+        cell(a.x) <= cell(b.x + b.w) && cell(b.x) <= cell(a.x + a.w) && cell(a.y) <= cell(b.y + b.h) && cell(b.y) <= cell(a.y + a.h)
+    }
+
+    fn random_rect(rng: &mut GameRng) -> Rect {
+        // Some of them reach past the grid on either side
+        Rect::new(rng.gen_range(-200.0..1100.0), rng.gen_range(-200.0..1100.0), rng.gen_range(1.0..250.0), rng.gen_range(1.0..250.0))
+    }
+
+    #[test]
+    fn queries_find_everything_a_brute_force_check_does() {
+        let mut rng = GameRng::seed_from_u64(3);
+        let mut grid = grid();
+        let rects: Vec<Rect> = (0..300).map(|_| random_rect(&mut rng)).collect();
+        for (id, rect) in rects.iter().enumerate() {
+            grid.insert(id, rect);
+        }
+
+        let mut found = Vec::new();
+        for _ in 0..300 {
+            let query = random_rect(&mut rng);
+            grid.query(&query, &mut found);
+
+            assert!(found.windows(2).all(|pair| pair[0] < pair[1]), "not sorted and unique: {:?}", found);
+            for (id, rect) in rects.iter().enumerate() {
+                if touches(rect, &query) {
+                    assert!(found.contains(&id), "{:?} misses {:?}", query, rect);
+                }
+            }
+            // And nothing that doesn't share a (clamped) cell with the query
+            let expected: Vec<usize> = (0..rects.len()).filter(|&id| shares_cell(&rects[id], &query)).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn outside_the_grid_goes_in_the_border_cells() {
+        let mut grid = grid();
+        grid.insert(1, &Rect::new(-500.0, -500.0, 10.0, 10.0));
+        grid.insert(2, &Rect::new(5000.0, 400.0, 10.0, 10.0));
+        let mut found = Vec::new();
+
+        grid.query(&Rect::new(10.0, 10.0, 1.0, 1.0), &mut found);
+        assert_eq!(found, vec![1]);
+        grid.query(&Rect::new(990.0, 450.0, 1.0, 1.0), &mut found);
+        assert_eq!(found, vec![2]);
+        grid.query(&Rect::new(-1000.0, 450.0, 1.0, 1.0), &mut found);
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn entities_over_several_cells_come_back_once() {
+        let mut grid = grid();
+        grid.insert(7, &Rect::new(50.0, 50.0, 300.0, 300.0));
+        let mut found = Vec::new();
+
+        grid.query(&Rect::new(0.0, 0.0, 1000.0, 1000.0), &mut found);
+        assert_eq!(found, vec![7]);
+
+        grid.clear();
+        grid.query(&Rect::new(0.0, 0.0, 1000.0, 1000.0), &mut found);
+        assert!(found.is_empty());
+    }
+}
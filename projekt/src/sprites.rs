@@ -0,0 +1,25 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::Image;
+
+use crate::catalogue::EnemyCatalogue;
+
+// All textures used while playing, loaded once and shared by the renderer
+pub struct Sprites {
+    pub player: Image,
+    pub enemies: Vec<Image>, // Same order as the enemy catalogue
+    pub background: Image,
+}
+
+impl Sprites {
+    pub fn new(ctx: &mut Context, catalogue: &EnemyCatalogue) -> GameResult<Sprites> {
+        let enemies = catalogue.enemies.iter()
+            .map(|def| Image::new(ctx, &def.sprite))
+            .collect::<GameResult<Vec<Image>>>()?;
+
+        Ok(Sprites {
+            player: Image::new(ctx, "/player.png")?,
+            enemies,
+            background: Image::new(ctx, "/tlo2.png")?,
+        })
+    }
+}
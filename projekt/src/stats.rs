@@ -0,0 +1,227 @@
+use serde::Deserialize;
+
+// Player stats that modifiers can change
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    MaxHp,
+    Damage,
+    Speed,
+    FireRate, // Seconds between shots
+    BulletSpeed,
+    Homing, // Degrees per second the player's bullets turn towards an enemy
+    Pierce, // Enemies a player bullet goes through before it is used up
+    Projectiles, // Bullets per shot, or drones, of a weapon
+    Spread, // Degrees a weapon's bullets are fanned across
+}
+
+const STAT_COUNT: usize = 9;
+
+impl Stat {
+    // Same order as the value arrays in Stats
+    pub const ALL: [Stat; STAT_COUNT] = [Stat::MaxHp, Stat::Damage, Stat::Speed, Stat::FireRate, Stat::BulletSpeed, Stat::Homing, Stat::Pierce,
+        Stat::Projectiles, Stat::Spread];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stat::MaxHp => "MAX HP",
+            Stat::Damage => "DAMAGE",
+            Stat::Speed => "SPEED",
+            Stat::FireRate => "FIRE RATE",
+            Stat::BulletSpeed => "BULLET SPEED",
+            Stat::Homing => "HOMING",
+            Stat::Pierce => "PIERCE",
+            Stat::Projectiles => "PROJECTILES",
+            Stat::Spread => "SPREAD",
+        }
+    }
+
+    // Lowest value that still makes sense whatever the modifiers say,
+    // a fire rate of 0 would fire every frame
+    pub fn floor(self) -> f32 {
+        match self {
+            Stat::MaxHp => 1.0,
+            Stat::Damage => 1.0,
+            Stat::Speed => 0.0,
+            Stat::FireRate => 0.05,
+            Stat::BulletSpeed => 50.0,
+            Stat::Homing => 0.0,
+            Stat::Pierce => 0.0,
+            Stat::Projectiles => 1.0,
+            Stat::Spread => 0.0,
+        }
+    }
+}
+
+// What a modifier does to its stat
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Add(f32),
+    Multiply(f32),
+}
+
+// Where a modifier came from, so it can be taken away again. Nothing hands
+// out power-ups or difficulty modifiers yet, only upgrades.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Upgrade(usize), // Index in the shop
+    PowerUp(usize), // A pickup, by an index its owner gives out
+    Difficulty,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Modifier {
+    pub stat: Stat,
+    pub effect: Effect,
+    pub source: Source,
+}
+
+// Base values plus the active modifiers. A final value is the base plus
+// every Add, times every Multiply, kept between the stat's limits.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    base: [f32; STAT_COUNT],
+    limits: [(f32, f32); STAT_COUNT], // Min and max of the final value
+    modifiers: Vec<Modifier>,
+    values: [f32; STAT_COUNT], // Final values, recomputed when the modifiers change
+}
+
+impl Stats {
+    // Stats left out start at 0
+    pub fn new(base: &[(Stat, f32)]) -> Stats {
+        Stats::with_floors(base, Stat::floor)
+    }
+
+    // For stats that mean something else than the player's, like a weapon's factors
+    pub fn with_floors(base: &[(Stat, f32)], floor: fn(Stat) -> f32) -> Stats {
+        let mut stats = Stats {
+            base: [0.0; STAT_COUNT],
+            limits: [(0.0, f32::INFINITY); STAT_COUNT],
+            modifiers: Vec::new(),
+            values: [0.0; STAT_COUNT],
+        };
+        for &(stat, value) in base {
+            stats.base[stat as usize] = value;
+        }
+        for stat in Stat::ALL {
+            stats.limits[stat as usize].0 = floor(stat);
+        }
+        stats.recompute();
+        stats
+    }
+
+    pub fn get(&self, stat: Stat) -> f32 {
+        self.values[stat as usize]
+    }
+
+    // For whole number stats like damage and max HP
+    pub fn get_i32(&self, stat: Stat) -> i32 {
+        self.get(stat).round() as i32
+    }
+
+    // Narrows the range the final value is kept in
+    pub fn limit(&mut self, stat: Stat, min: Option<f32>, max: Option<f32>) {
+        let (low, high) = &mut self.limits[stat as usize];
+        if let Some(min) = min {
+            *low = low.max(min);
+        }
+        if let Some(max) = max {
+            *high = high.min(max);
+        }
+        self.recompute();
+    }
+
+    pub fn add(&mut self, modifier: Modifier) {
+        self.modifiers.push(modifier);
+        self.recompute();
+    }
+
+    // Takes away the newest modifier from `source`
+    pub fn remove_last(&mut self, source: Source) -> bool {
+        match self.modifiers.iter().rposition(|modifier| modifier.source == source) {
+            Some(i) => {
+                self.modifiers.remove(i);
+                self.recompute();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Takes away every modifier from `source`, like a power-up running out
+    #[allow(dead_code)]
+    pub fn remove_source(&mut self, source: Source) -> usize {
+        let before = self.modifiers.len();
+        self.modifiers.retain(|modifier| modifier.source != source);
+        self.recompute();
+        before - self.modifiers.len()
+    }
+
+    // The final value the stat would have with one more modifier
+    pub fn with(&self, modifier: Modifier) -> f32 {
+        self.compute(modifier.stat, self.modifiers.iter().chain(std::iter::once(&modifier)))
+    }
+
+    fn compute<'a>(&self, stat: Stat, modifiers: impl Iterator<Item = &'a Modifier>) -> f32 {
+        let (mut added, mut factor) = (self.base[stat as usize], 1.0);
+        for modifier in modifiers.filter(|modifier| modifier.stat == stat) {
+            match modifier.effect {
+                Effect::Add(amount) => added += amount,
+                Effect::Multiply(by) => factor *= by,
+            }
+        }
+        let (min, max) = self.limits[stat as usize];
+        // Not clamp, a max below the floor would make it panic
+        (added * factor).max(min).min(max)
+    }
+
+    fn recompute(&mut self) {
+        for stat in Stat::ALL {
+            self.values[stat as usize] = self.compute(stat, self.modifiers.iter());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modifier(stat: Stat, effect: Effect, source: Source) -> Modifier {
+        Modifier { stat, effect, source }
+    }
+
+    #[test]
+    fn every_source_can_be_taken_away() {
+        let mut stats = Stats::new(&[(Stat::Speed, 100.0)]);
+        for source in [Source::Upgrade(0), Source::PowerUp(3), Source::Difficulty] {
+            stats.add(modifier(Stat::Speed, Effect::Add(50.0), source));
+            stats.add(modifier(Stat::Speed, Effect::Multiply(2.0), source));
+        }
+        assert_eq!(stats.get(Stat::Speed), 250.0 * 8.0);
+
+        assert_eq!(stats.remove_source(Source::PowerUp(3)), 2);
+        assert_eq!(stats.get(Stat::Speed), 200.0 * 4.0);
+        assert_eq!(stats.remove_source(Source::PowerUp(3)), 0);
+        assert_eq!(stats.remove_source(Source::PowerUp(1)), 0);
+        assert_eq!(stats.remove_source(Source::Difficulty), 2);
+        assert_eq!(stats.get(Stat::Speed), 150.0 * 2.0);
+        assert!(stats.remove_last(Source::Upgrade(0)));
+        assert_eq!(stats.get(Stat::Speed), 150.0);
+    }
+
+    #[test]
+    fn floors_and_limits_hold() {
+        let mut stats = Stats::new(&[(Stat::Damage, 2.0)]);
+        stats.add(modifier(Stat::Damage, Effect::Multiply(0.25), Source::Upgrade(0)));
+        assert_eq!(stats.get(Stat::Damage), Stat::Damage.floor());
+
+        stats.limit(Stat::Damage, None, Some(0.5));
+        assert_eq!(stats.get(Stat::Damage), 0.5);
+
+        let mut factors = Stats::with_floors(&[(Stat::Damage, 1.0)], |_| 0.0);
+        factors.add(modifier(Stat::Damage, Effect::Multiply(0.25), Source::Upgrade(0)));
+        assert_eq!(factors.get(Stat::Damage), 0.25);
+    }
+}
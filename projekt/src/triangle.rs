@@ -1,59 +1,63 @@
-use ggez::{Context, GameResult};
-use ggez::graphics::{self, DrawParam, Image};
+use ggez::GameResult;
+use ggez::graphics::DrawParam;
 use nalgebra as na;
 use crate::player::Player;
-use crate::bullet::Bullet;
+use crate::bullet::BulletPool;
 use crate::enemy::Enemy;
+use crate::render::Renderer;
+use crate::catalogue::{EnemyCatalogue, EnemyDef};
+use crate::collider::Collider;
+use crate::world::{interpolate, GameRng};
 
 pub struct TriangleEnemy {
-    pub size: f32,
     pub pos: na::Point2<f32>,
+    pub prev_pos: na::Point2<f32>,
     pub hp: i32,
     pub speed: f32,
     pub damage: i32,
     pub coins: i32,
     pub points: i32,
-    pub sprite: Image
+    pub collider: Collider,
+    pub archetype: usize,
+    pub sprite_scale: f32,
 }
 
 impl TriangleEnemy {
-    pub fn new(pos: na::Point2<f32>, level: i32, image: Image) -> Self {
+    pub fn new(pos: na::Point2<f32>, level: i32, def: &EnemyDef, archetype: usize) -> Self {
         TriangleEnemy {
-            size: 30.0,
             pos,
-            hp: 1 * level,
-            speed: 6.0 * level as f32 / 2.0,
-            damage: 1 * level,
-            coins: 50 * level,
-            points: 10 * level,
-            sprite: image
+            prev_pos: pos,
+            hp: def.hp.at_i32(level),
+            speed: def.speed.at(level),
+            damage: def.damage.at_i32(level),
+            coins: def.coins.at_i32(level),
+            points: def.points.at_i32(level),
+            collider: def.collider(),
+            archetype,
+            sprite_scale: def.sprite_scale,
         }
     }
 
-    fn move_towards_player(&mut self, player_pos: &na::Point2<f32>) {
+    fn move_towards_player(&mut self, player_pos: &na::Point2<f32>, dt: f32) {
         let direction = (player_pos - self.pos).normalize();
-        self.pos += direction * self.speed;
+        self.pos += direction * self.speed * dt;
     }
 }
 
 impl Enemy for TriangleEnemy {
-    fn update(&mut self, player: &Player, _ctx: &mut Context, _game_bullets: &mut Vec<Bullet>) {
-        self.move_towards_player(&player.player_pos);
+    fn update(&mut self, player: &Player, dt: f32, _game_bullets: &mut BulletPool, _catalogue: &EnemyCatalogue, _rng: &mut GameRng) {
+        self.prev_pos = self.pos;
+        self.move_towards_player(&player.player_pos, dt);
     }
 
-    fn draw(&self, ctx: &mut Context) -> GameResult {
-        graphics::draw(ctx, &self.sprite, DrawParam::default().dest([self.pos.x, self.pos.y]).scale([1.5, 1.5]))?;
+    fn draw(&self, _catalogue: &EnemyCatalogue, renderer: &mut Renderer, alpha: f32) -> GameResult {
+        let pos = interpolate(&self.prev_pos, &self.pos, alpha);
+        renderer.enemy(self.archetype, DrawParam::default().dest([pos.x, pos.y]).scale([self.sprite_scale, self.sprite_scale]));
         Ok(())
     }
 
-    fn check_collision(&self, player: &Player) -> bool {
-        let enemy_rect = graphics::Rect::new(self.pos.x - 10.0, self.pos.y - 10.0, self.size, self.size);
-        let player_rect = graphics::Rect::new(player.player_pos.x - 10.0, player.player_pos.y - 10.0, 20.0, 20.0);
-        enemy_rect.overlaps(&player_rect)
-    }
-
-    fn apply_damage(&self, player: &mut Player) {
-        player.take_damage(self.damage);
+    fn contact_damage(&self) -> i32 {
+        self.damage
     }
 
     fn take_damage(&mut self, damage: i32) -> i32{
@@ -64,6 +68,10 @@ impl Enemy for TriangleEnemy {
         self.hp
     }
 
+    fn archetype(&self) -> usize {
+        self.archetype
+    }
+
     fn get_pos(&self) -> &na::Point2<f32> {
         &self.pos
     }
@@ -80,7 +88,7 @@ impl Enemy for TriangleEnemy {
         self.points
     }
 
-    fn get_size(&self) -> f32 {
-        self.size
+    fn collider(&self) -> &Collider {
+        &self.collider
     }
 }
\ No newline at end of file
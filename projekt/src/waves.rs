@@ -0,0 +1,354 @@
+use std::f32::consts::PI;
+
+use ggez::{GameError, GameResult};
+use nalgebra as na;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::catalogue::{Behaviour, EnemyCatalogue};
+use crate::resources::load_toml;
+use crate::world::GameRng;
+use crate::playfield::{self, HEIGHT, WIDTH};
+
+// Where the enemies of a spawn group appear
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    Edge,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    Ring,
+    Line,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpawnGroup {
+    pub enemy: String,
+    pub count: u32,
+    pub placement: Placement,
+    #[serde(default)]
+    pub delay: f32,
+    #[serde(default)]
+    pub interval: f32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveDef {
+    pub groups: Vec<SpawnGroup>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct StageDef {
+    pub waves: Vec<WaveDef>,
+    pub boss: String,
+}
+
+// Contents of resources/waves.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveScript {
+    pub clear_pause: f32,
+    pub stages: Vec<StageDef>,
+}
+
+impl WaveScript {
+    pub fn load() -> GameResult<WaveScript> {
+        load_toml("waves.toml")
+    }
+}
+
+// Random point on one of the 4 screen edges
+pub fn random_edge_point(rng: &mut GameRng) -> na::Point2<f32> {
+    match rng.gen_range(0..4) {
+        0 => na::Point2::new(rng.gen_range(0.0..WIDTH), 0.0),    // Top edge
+        1 => na::Point2::new(WIDTH, rng.gen_range(0.0..HEIGHT)), // Right edge
+        2 => na::Point2::new(rng.gen_range(0.0..WIDTH), HEIGHT), // Bottom edge
+        _ => na::Point2::new(0.0, rng.gen_range(0.0..HEIGHT)),   // Left edge
+    }
+}
+
+// Position of the i-th of `count` enemies in a group
+fn placement_point(placement: Placement, i: u32, count: u32, rng: &mut GameRng) -> na::Point2<f32> {
+    match placement {
+        Placement::Edge => random_edge_point(rng),
+        Placement::Top => na::Point2::new(rng.gen_range(0.0..WIDTH), 0.0),
+        Placement::Bottom => na::Point2::new(rng.gen_range(0.0..WIDTH), HEIGHT),
+        Placement::Left => na::Point2::new(0.0, rng.gen_range(0.0..HEIGHT)),
+        Placement::Right => na::Point2::new(WIDTH, rng.gen_range(0.0..HEIGHT)),
+        Placement::Ring => {
+            let angle = i as f32 / count as f32 * 2.0 * PI;
+            let center = playfield::center();
+            na::Point2::new(center.x + angle.cos() * (center.x - 50.0), center.y + angle.sin() * center.y)
+        }
+        Placement::Line => na::Point2::new(WIDTH * (i as f32 + 0.5) / count as f32, 0.0),
+    }
+}
+
+// Spawn group with the enemy name resolved to a catalogue index
+struct Group {
+    archetype: usize,
+    count: u32,
+    placement: Placement,
+    delay: f32,
+    interval: f32,
+    spawned: u32,
+}
+
+// One entry of the timeline: a normal wave or a stage's boss wave
+struct Wave {
+    stage: usize,
+    number: usize,
+    is_boss: bool,
+    groups: Vec<Group>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WavePhase {
+    Running,
+    Cleared { pause_left: f32 },
+}
+
+pub enum WaveEvent {
+    Spawn { archetype: usize, pos: na::Point2<f32> },
+    WaveCleared,
+}
+
+// Runs the wave script: tells the world what to spawn and when a wave is cleared
+pub struct WaveDirector {
+    waves: Vec<Wave>,
+    clear_pause: f32,
+    current: usize,
+    wave_time: f32,
+    pub phase: WavePhase,
+}
+
+impl WaveDirector {
+    pub fn new(script: &WaveScript, catalogue: &EnemyCatalogue) -> GameResult<WaveDirector> {
+        let archetype = |name: &str| {
+            catalogue.enemies.iter().position(|def| def.name == name).ok_or_else(|| {
+                GameError::ResourceLoadError(format!("resources/waves.toml: unknown enemy {}", name))
+            })
+        };
+
+        let mut waves = Vec::new();
+        for (stage, stage_def) in script.stages.iter().enumerate() {
+            for (number, wave_def) in stage_def.waves.iter().enumerate() {
+                let mut groups = Vec::new();
+                for group in &wave_def.groups {
+                    groups.push(Group {
+                        archetype: archetype(&group.enemy)?,
+                        count: group.count,
+                        placement: group.placement,
+                        delay: group.delay,
+                        interval: group.interval,
+                        spawned: 0,
+                    });
+                }
+                waves.push(Wave { stage, number, is_boss: false, groups });
+            }
+
+            // Anything else would come in as a normal enemy and the stage would have no boss
+            let boss_archetype = archetype(&stage_def.boss)?;
+            if catalogue.enemies[boss_archetype].behaviour != Behaviour::Boss {
+                return Err(GameError::ResourceLoadError(format!(
+                    "resources/waves.toml: stage {} boss {} does not have boss behaviour", stage + 1, stage_def.boss)));
+            }
+            let boss = Group { archetype: boss_archetype, count: 1, placement: Placement::Top, delay: 1.0, interval: 0.0, spawned: 0 };
+            waves.push(Wave { stage, number: stage_def.waves.len(), is_boss: true, groups: vec![boss] });
+        }
+
+        if waves.iter().all(|wave| wave.groups.iter().all(|group| group.count == 0)) {
+            return Err(GameError::ResourceLoadError("resources/waves.toml: no wave spawns anything".to_string()));
+        }
+
+        Ok(WaveDirector { waves, clear_pause: script.clear_pause, current: 0, wave_time: 0.0, phase: WavePhase::Running })
+    }
+
+    // Text for the HUD, e.g. "STAGE 1 - WAVE 2"
+    pub fn wave_name(&self) -> String {
+        let wave = &self.waves[self.current];
+        if wave.is_boss {
+            format!("STAGE {} - BOSS", wave.stage + 1)
+        } else {
+            format!("STAGE {} - WAVE {}", wave.stage + 1, wave.number + 1)
+        }
+    }
+
+    fn all_spawned(&self) -> bool {
+        self.waves[self.current].groups.iter().all(|group| group.spawned >= group.count)
+    }
+
+    fn start_next_wave(&mut self) {
+        // After the last stage start again from the first one
+        self.current = (self.current + 1) % self.waves.len();
+        self.wave_time = 0.0;
+        for group in &mut self.waves[self.current].groups {
+            group.spawned = 0;
+        }
+        self.phase = WavePhase::Running;
+    }
+
+    // Adds what happened this tick to `events`
+    pub fn update(&mut self, dt: f32, enemies_alive: usize, rng: &mut GameRng, events: &mut Vec<WaveEvent>) {
+        let spawned_before = events.len();
+        match self.phase {
+            WavePhase::Cleared { pause_left } => {
+                if pause_left - dt <= 0.0 {
+                    self.start_next_wave();
+                } else {
+                    self.phase = WavePhase::Cleared { pause_left: pause_left - dt };
+                }
+            }
+            WavePhase::Running => {
+                self.wave_time += dt;
+                let wave_time = self.wave_time;
+
+                // Spawn every enemy whose time has come
+                for group in &mut self.waves[self.current].groups {
+                    while group.spawned < group.count
+                        && wave_time >= group.delay + group.interval * group.spawned as f32 {
+                        let pos = placement_point(group.placement, group.spawned, group.count, rng);
+                        events.push(WaveEvent::Spawn { archetype: group.archetype, pos });
+                        group.spawned += 1;
+                    }
+                }
+
+                if events.len() == spawned_before && enemies_alive == 0 && self.all_spawned() {
+                    self.phase = WavePhase::Cleared { pause_left: self.clear_pause };
+                    events.push(WaveEvent::WaveCleared);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::world::FIXED_DT;
+
+    // A chaser called "bomb" and a boss called "boss", so nothing depends on resources/
+    fn catalogue() -> EnemyCatalogue {
+        toml::from_str(r#"
+            [[enemies]]
+            name = "bomb"
+            behaviour = "chaser"
+            sprite = "/bomb.png"
+            sprite_scale = 1.0
+            collider = "triangle"
+            hp = { base = 1 }
+            speed = { base = 100 }
+            damage = { base = 1 }
+            coins = { base = 5 }
+            points = { base = 10 }
+
+            [[enemies]]
+            name = "boss"
+            behaviour = "boss"
+            sprite = "/boss.png"
+            sprite_scale = 1.0
+            collider = "box"
+            hp = { base = 100 }
+            speed = { base = 50 }
+            damage = { base = 5 }
+            coins = { base = 500 }
+            points = { base = 1000 }
+            bullet_speed = { base = 300 }
+        "#).unwrap()
+    }
+
+    fn director(script: &str) -> GameResult<WaveDirector> {
+        let script: WaveScript = toml::from_str(script).unwrap();
+        WaveDirector::new(&script, &catalogue())
+    }
+
+    fn load_error(script: &str) -> String {
+        match director(script) {
+            Err(GameError::ResourceLoadError(message)) => message,
+            Err(other) => panic!("wrong error: {:?}", other),
+            Ok(_) => panic!("loaded a broken wave script"),
+        }
+    }
+
+    // Runs the director for `seconds` with `enemies_alive` and returns what it spawned
+    fn run(director: &mut WaveDirector, seconds: f32, enemies_alive: usize, rng: &mut GameRng) -> (Vec<usize>, usize) {
+        let mut events = Vec::new();
+        for _ in 0..(seconds / FIXED_DT).round() as usize {
+            director.update(FIXED_DT, enemies_alive, rng, &mut events);
+        }
+        let spawned = events.iter().filter_map(|event| match event {
+            WaveEvent::Spawn { archetype, .. } => Some(*archetype),
+            WaveEvent::WaveCleared => None,
+        }).collect();
+        let cleared = events.iter().filter(|event| matches!(event, WaveEvent::WaveCleared)).count();
+        (spawned, cleared)
+    }
+
+    #[test]
+    fn waves_then_the_boss_then_around_again() {
+        let mut director = director(r#"
+            clear_pause = 1.0
+
+            [[stages]]
+            boss = "boss"
+
+              [[stages.waves]]
+              groups = [{ enemy = "bomb", count = 3, placement = "line", delay = 0.5, interval = 0.5 }]
+        "#).unwrap();
+        let mut rng = GameRng::seed_from_u64(1);
+        assert_eq!(director.wave_name(), "STAGE 1 - WAVE 1");
+
+        // Enemies come in on their delay and interval and the wave waits for them to die
+        assert_eq!(run(&mut director, 1.1, 1, &mut rng), (vec![0, 0], 0));
+        assert_eq!(run(&mut director, 1.0, 1, &mut rng), (vec![0], 0));
+        assert_eq!(director.phase, WavePhase::Running);
+        assert_eq!(run(&mut director, 0.1, 0, &mut rng), (vec![], 1));
+
+        // Boss wave after the pause, then back to the first wave
+        assert_eq!(run(&mut director, 1.0, 0, &mut rng), (vec![], 0));
+        assert_eq!(director.wave_name(), "STAGE 1 - BOSS");
+        assert_eq!(run(&mut director, 1.1, 1, &mut rng), (vec![1], 0));
+        assert_eq!(run(&mut director, 1.1, 0, &mut rng), (vec![], 1));
+        assert_eq!(director.wave_name(), "STAGE 1 - WAVE 1");
+    }
+
+    #[test]
+    fn stage_boss_must_have_boss_behaviour() {
+        let message = load_error(r#"
+            clear_pause = 1.0
+
+            [[stages]]
+            boss = "bomb"
+
+              [[stages.waves]]
+              groups = [{ enemy = "bomb", count = 3, placement = "edge" }]
+        "#);
+        assert_eq!(message, "resources/waves.toml: stage 1 boss bomb does not have boss behaviour");
+    }
+
+    #[test]
+    fn unknown_enemies_are_refused() {
+        let message = load_error(r#"
+            clear_pause = 1.0
+
+            [[stages]]
+            boss = "boss"
+
+              [[stages.waves]]
+              groups = [{ enemy = "bmob", count = 3, placement = "edge" }]
+        "#);
+        assert_eq!(message, "resources/waves.toml: unknown enemy bmob");
+
+        let message = load_error(r#"
+            clear_pause = 1.0
+
+            [[stages]]
+            boss = "bos"
+            waves = []
+        "#);
+        assert_eq!(message, "resources/waves.toml: unknown enemy bos");
+    }
+}
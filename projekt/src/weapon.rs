@@ -0,0 +1,170 @@
+use ggez::{GameError, GameResult};
+use ggez::graphics::{Color, Rect};
+use nalgebra as na;
+use serde::Deserialize;
+
+use crate::replay::MAX_WEAPONS;
+use crate::resources::load_toml;
+use crate::stats::{Stat, Stats};
+
+// Stats a weapon's own upgrades can change. Damage and fire rate are factors
+// on the player's stats, starting at 1; the rest add to the weapon's values.
+pub const WEAPON_STATS: [Stat; 5] = [Stat::Damage, Stat::FireRate, Stat::Projectiles, Stat::Spread, Stat::Pierce];
+
+// How a weapon turns a trigger pull into damage
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Gun,    // Bullets from the player, `projectiles` of them fanned across `spread` degrees
+    Laser,  // A beam hurting the closest enemy along it, and `pierce` more behind it, every tick it is held
+    Drones, // `projectiles` drones circling the player, each firing a bullet at the aim
+}
+
+// One weapon as written in resources/weapons.toml
+#[derive(Deserialize, Clone, Debug)]
+pub struct WeaponDef {
+    pub id: String,
+    pub name: String,
+    pub kind: Kind,
+    #[serde(default = "default_multiplier")]
+    pub fire_rate: f32, // Times the player's seconds between shots
+    #[serde(default = "default_multiplier")]
+    pub damage: f32,
+    #[serde(default = "default_multiplier")]
+    pub speed: f32,
+    #[serde(default = "default_size")]
+    pub size: f32, // Bullet size, or the width of a laser
+    #[serde(default = "default_projectiles")]
+    pub projectiles: u32,
+    #[serde(default)]
+    pub spread: f32, // Degrees
+    #[serde(default = "default_burst")]
+    pub burst: u32, // Shots per trigger pull
+    #[serde(default)]
+    pub burst_interval: f32,
+    #[serde(default)]
+    pub range: f32, // Laser length
+    #[serde(default)]
+    pub orbit_radius: f32,
+    #[serde(default)]
+    pub orbit_speed: f32, // Degrees per second
+    #[serde(default = "default_color")]
+    pub color: [u8; 3], // Of the laser and the drones
+}
+
+fn default_multiplier() -> f32 {
+    1.0
+}
+
+fn default_size() -> f32 {
+    10.0
+}
+
+fn default_projectiles() -> u32 {
+    1
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+fn default_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+impl WeaponDef {
+    pub fn color(&self) -> Color {
+        Color::from_rgb(self.color[0], self.color[1], self.color[2])
+    }
+}
+
+// Contents of resources/weapons.toml, the first weapon is the one the player starts with
+#[derive(Deserialize, Clone, Debug)]
+pub struct WeaponLibrary {
+    pub weapons: Vec<WeaponDef>,
+}
+
+impl WeaponLibrary {
+    pub fn load() -> GameResult<WeaponLibrary> {
+        let library: WeaponLibrary = load_toml("weapons.toml")?;
+        let error = |message: String| Err(GameError::ResourceLoadError(format!("resources/weapons.toml: {}", message)));
+
+        if library.weapons.is_empty() || library.weapons.len() > MAX_WEAPONS {
+            return error(format!("needs 1 to {} weapons", MAX_WEAPONS));
+        }
+        for (i, def) in library.weapons.iter().enumerate() {
+            if library.weapons[..i].iter().any(|other| other.id == def.id) {
+                return error(format!("{} is listed twice", def.id));
+            }
+            if def.fire_rate <= 0.0 || def.projectiles == 0 || def.burst == 0 {
+                return error(format!("{} needs a positive fire_rate and at least one projectile and burst shot", def.id));
+            }
+            if def.burst > 1 && (def.kind == Kind::Laser || def.burst_interval <= 0.0) {
+                return error(format!("{} fires bursts, which need a positive burst_interval and no laser", def.id));
+            }
+            if def.kind == Kind::Laser && def.range <= 0.0 {
+                return error(format!("{} is a laser without a range", def.id));
+            }
+            if def.kind == Kind::Drones && def.orbit_radius <= 0.0 {
+                return error(format!("{} has drones without an orbit_radius", def.id));
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn find(&self, id: &str) -> Option<usize> {
+        self.weapons.iter().position(|def| def.id == id)
+    }
+}
+
+// A weapon the player carries, with the upgrades bought for it
+#[derive(Clone, Debug)]
+pub struct Weapon {
+    pub def: WeaponDef,
+    pub stats: Stats, // Only WEAPON_STATS are used
+}
+
+// Lowest values of a weapon's own stats. Its damage and fire rate are
+// factors on the player's, the floors of those hold for the final values.
+fn weapon_floor(stat: Stat) -> f32 {
+    match stat {
+        Stat::Projectiles => 1.0,
+        _ => 0.0,
+    }
+}
+
+impl Weapon {
+    pub fn new(def: WeaponDef) -> Weapon {
+        let stats = Stats::with_floors(&[
+            (Stat::Damage, 1.0),
+            (Stat::FireRate, 1.0),
+            (Stat::Projectiles, def.projectiles as f32),
+            (Stat::Spread, def.spread),
+        ], weapon_floor);
+        Weapon { def, stats }
+    }
+}
+
+// The laser while the trigger is held, the world finds the enemies along it
+#[derive(Clone, Copy, Debug)]
+pub struct Beam {
+    pub start: na::Point2<f32>,
+    pub direction: na::Vector2<f32>, // Unit length
+    pub length: f32, // The range, cut short by the world at the last enemy it stops at
+    pub width: f32,
+    pub damage: f32, // Per second, the world deals it a tick at a time
+    pub pierce: u32,
+    pub color: Color,
+}
+
+impl Beam {
+    pub fn end(&self) -> na::Point2<f32> {
+        self.start + self.direction * self.length
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let end = self.end();
+        let (min, max) = (self.start.coords.inf(&end.coords), self.start.coords.sup(&end.coords));
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+}
@@ -0,0 +1,682 @@
+use crate::player::Player;
+use crate::enemy::Enemy;
+
+use crate::triangle::TriangleEnemy;
+use crate::hexagonal::HexagonEnemy;
+use crate::boss::Boss;
+
+use crate::bullet::{BulletPool, Team, BULLET_CAPACITY};
+use crate::shop::{Shop, UpgradeTree};
+use crate::input::InputState;
+use crate::catalogue::{Behaviour, EnemyCatalogue};
+use crate::waves::{random_edge_point, WaveDirector, WaveEvent, WavePhase, WaveScript};
+use crate::spatial::SpatialGrid;
+use crate::arena::{Arena, Id};
+use crate::bomb::{BombPickup, Shockwave, MAX_BOMBS};
+use crate::pattern::{self, Emitter};
+use crate::weapon::WeaponLibrary;
+
+use crate::resources::image_size;
+use crate::playfield;
+
+use ggez::GameResult;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use nalgebra as na;
+
+// Every random roll in a run comes from one of these, so a seed replays the run exactly
+pub type GameRng = StdRng;
+
+// The simulation always advances in fixed steps of FIXED_DT seconds
+pub const TICK_RATE: u32 = 60;
+pub const FIXED_DT: f32 = 1.0 / TICK_RATE as f32;
+
+// Size of a broadphase cell, a bit bigger than the biggest enemy
+const GRID_CELL_SIZE: f32 = 128.0;
+// Anything no bigger than a cell lies in at most 4 of them
+const CELLS_PER_ENTITY: usize = 4;
+
+// Room reserved up front for the per-tick buffers, so play that stays under
+// these allocates nothing once it is running
+const ENEMY_CAPACITY: usize = 1024;
+const PICKUP_CAPACITY: usize = 64;
+const BURST_CAPACITY: usize = 256; // Bullets bursting into patterns at once
+const WAVE_EVENT_CAPACITY: usize = 64;
+
+// Position between the previous and current update, used to render smoothly between steps
+pub fn interpolate(prev: &na::Point2<f32>, current: &na::Point2<f32>, alpha: f32) -> na::Point2<f32> {
+    na::Point2::from(prev.coords.lerp(&current.coords, alpha))
+}
+
+// Entities to remove at the end of the tick. Ids can be queued more than
+// once, removing an Id that is already gone does nothing.
+struct DespawnQueue {
+    enemies: Vec<Id>,
+    bullets: Vec<Id>,
+}
+
+impl DespawnQueue {
+    fn new() -> DespawnQueue {
+        DespawnQueue { enemies: Vec::with_capacity(ENEMY_CAPACITY), bullets: Vec::with_capacity(BULLET_CAPACITY) }
+    }
+}
+
+// Whole game simulation, independent of ggez Context so it can run without a window
+pub struct World {
+    pub player: Player,
+    pub shop: Shop,
+    pub enemies: Arena<Box<dyn Enemy>>,
+    pub bullets: BulletPool, // Both teams
+    pub is_boss: bool,
+    pub level: i32,
+    pub killed_enemies: i32,
+    pub spawn_rate: f32,
+    pub time: f32,
+    pub frame: u64,
+    pub seed: u64,
+    pub rng: GameRng,
+    pub catalogue: EnemyCatalogue,
+    pub waves: Option<WaveDirector>, // None in endless mode
+    pub enemy_grid: SpatialGrid<Id>,
+    pub bullet_grid: SpatialGrid<Id>,
+    pub shockwaves: Vec<Shockwave>,
+    pub pickups: Vec<BombPickup>,
+    pub bursts: Emitter, // Patterns of bullets that burst into child bullets
+    bomb_held: bool, // Bombs go off on the press, holding the button doesn't chain them
+    candidates: Vec<Id>,
+    beam_hits: Vec<(f32, Id)>, // Enemies along the laser and how far along it they are
+    beam_damage: Vec<(Id, f32)>, // Laser damage enemies took short of a whole point, kept while they stay in the beam
+    contact_cooldowns: Vec<(Id, f32)>, // Enemies that touched the player and seconds until they can hurt again
+    wave_events: Vec<WaveEvent>,
+    despawn: DespawnQueue,
+}
+
+impl World {
+    // Loads enemies and waves from resources; endless mode spawns randomly instead of following waves
+    pub fn load(seed: u64, endless: bool) -> GameResult<World> {
+        let catalogue = EnemyCatalogue::load()?;
+        let waves = if endless {
+            None
+        } else {
+            Some(WaveDirector::new(&WaveScript::load()?, &catalogue)?)
+        };
+        let weapons = WeaponLibrary::load()?;
+        let mut player = Player::new(image_size("/player.png")?, &weapons)?;
+        let shop = Shop::new(UpgradeTree::load(&weapons)?);
+        shop.limit_stats(&mut player);
+        Ok(World::new(seed, player, shop, catalogue, waves))
+    }
+
+    pub fn new(seed: u64, player: Player, shop: Shop, catalogue: EnemyCatalogue, waves: Option<WaveDirector>) -> World {
+        World {
+            player,
+            shop,
+            enemies: Arena::new(),
+            bullets: BulletPool::with_capacity(BULLET_CAPACITY),
+            is_boss: false,
+            level: 1,
+            killed_enemies: 0,
+            spawn_rate: 0.02,
+            time: 0.0,
+            frame: 0,
+            seed,
+            rng: GameRng::seed_from_u64(seed),
+            catalogue,
+            waves,
+            enemy_grid: World::new_grid(ENEMY_CAPACITY),
+            bullet_grid: World::new_grid(BULLET_CAPACITY),
+            shockwaves: Vec::new(),
+            pickups: Vec::with_capacity(PICKUP_CAPACITY),
+            bursts: Emitter::with_capacity(BURST_CAPACITY),
+            bomb_held: false,
+            candidates: Vec::with_capacity(BULLET_CAPACITY),
+            beam_hits: Vec::with_capacity(ENEMY_CAPACITY),
+            beam_damage: Vec::with_capacity(ENEMY_CAPACITY),
+            contact_cooldowns: Vec::with_capacity(ENEMY_CAPACITY),
+            wave_events: Vec::with_capacity(WAVE_EVENT_CAPACITY),
+            despawn: DespawnQueue::new(),
+        }
+    }
+
+    fn new_grid(entities: usize) -> SpatialGrid<Id> {
+        let m = playfield::GRID_MARGIN;
+        SpatialGrid::new(-m, -m, playfield::WIDTH + m, playfield::HEIGHT + m, GRID_CELL_SIZE, entities * CELLS_PER_ENTITY)
+    }
+
+    // Endless mode: random enemy on a random edge
+    pub fn spawn_enemy(&mut self) {
+        let pos = random_edge_point(&mut self.rng);
+
+        // Pick an archetype from the catalogue by its spawn weight
+        let archetype = self.catalogue.pick(self.rng.gen_range(0..self.catalogue.total_spawn_weight()));
+        let def = &self.catalogue.enemies[archetype];
+        if self.rng.gen::<f32>() >= def.spawn_chance {
+            return;
+        }
+
+        // Only one boss at a time
+        if def.behaviour == Behaviour::Boss && self.is_boss {
+            return;
+        }
+        self.spawn_archetype(archetype, pos);
+    }
+
+    pub fn spawn_archetype(&mut self, archetype: usize, pos: na::Point2<f32>) {
+        let def = &self.catalogue.enemies[archetype];
+        match def.behaviour {
+            Behaviour::Chaser => {
+                self.enemies.insert(Box::new(TriangleEnemy::new(pos, self.level, def, archetype)));
+            }
+            Behaviour::Shooter => {
+                self.enemies.insert(Box::new(HexagonEnemy::new(pos, self.level, def, archetype)));
+            }
+            Behaviour::Boss => {
+                self.enemies.insert(Box::new(Boss::new(pos, self.level, def, archetype)));
+                self.is_boss = true;
+            }
+        }
+    }
+
+    fn update_endless_spawning(&mut self) {
+        // Spawn enemies randomly
+        if self.rng.gen::<f32>() < self.spawn_rate {
+            self.spawn_enemy();
+        }
+
+        // Check if level should be increased
+        if self.killed_enemies >= 30 {
+            self.level += 1;
+            self.killed_enemies = 0;
+            self.spawn_rate += 0.01;
+        }
+    }
+
+    fn update_wave_spawning(&mut self, dt: f32) {
+        let mut events = std::mem::take(&mut self.wave_events);
+        if let Some(director) = &mut self.waves {
+            director.update(dt, self.enemies.len(), &mut self.rng, &mut events);
+        }
+
+        for event in events.drain(..) {
+            match event {
+                WaveEvent::Spawn { archetype, pos } => self.spawn_archetype(archetype, pos),
+                WaveEvent::WaveCleared => {
+                    // Every wave is one level
+                    self.level += 1;
+                    self.killed_enemies = 0;
+                }
+            }
+        }
+        self.wave_events = events;
+    }
+
+    // Purchases and refunds confirmed on the shop screen
+    fn handle_shop_buy(&mut self, input: &InputState) {
+        if let Some(upgrade) = input.buy {
+            self.shop.try_buy(upgrade, &mut self.player);
+        }
+        if input.undo {
+            self.shop.undo(&mut self.player);
+        }
+    }
+
+    // The pause after a cleared wave, when the shop can be opened without pausing
+    pub fn between_waves(&self) -> bool {
+        matches!(&self.waves, Some(waves) if matches!(waves.phase, WavePhase::Cleared { .. }))
+    }
+
+    fn update_enemies(&mut self, dt: f32) {
+        // Update all enemies and put them in the broadphase grid
+        self.enemy_grid.clear();
+        for (id, enemy) in self.enemies.iter_mut() {
+            enemy.update(&self.player, dt, &mut self.bullets, &self.catalogue, &mut self.rng); // Udpating
+            self.enemy_grid.insert(id, &enemy.bounds());
+        }
+
+        // Enemies touching the player, each one hurts at most once per its contact_cooldown
+        self.contact_cooldowns.retain_mut(|(_, left)| {
+            *left -= dt;
+            *left > 0.0
+        });
+        self.enemy_grid.query(&self.player.bounds(), &mut self.candidates);
+        for &id in &self.candidates {
+            let enemy = &self.enemies[id];
+            let cooling_down = self.contact_cooldowns.iter().any(|(source, _)| *source == id);
+            if cooling_down || !enemy.check_collision(&self.player) {
+                continue;
+            }
+            let def = &self.catalogue.enemies[enemy.archetype()];
+            if self.player.hit(enemy.contact_damage(), &enemy.collider().center(enemy.get_pos()), def.knockback) {
+                self.contact_cooldowns.push((id, def.contact_cooldown));
+            }
+        }
+
+    }
+
+    // Moves every bullet and puts it in the broadphase grid. Homing player
+    // bullets turn towards `homing_target`, enemy ones towards the player.
+    fn move_bullets(&mut self, dt: f32, homing_target: Option<na::Point2<f32>>) {
+        self.bullet_grid.clear();
+        let player_pos = Some(self.player.player_pos);
+        for (id, bullet) in self.bullets.iter_mut() {
+            let target = match bullet.team {
+                Team::Player => homing_target,
+                Team::Enemy => player_pos,
+            };
+            bullet.update(dt, target);
+            self.bullet_grid.insert(id, &bullet.bounds());
+        }
+
+        pattern::expire_bullets(&self.bullets, &mut self.bursts, &mut self.despawn.bullets);
+        let target = self.player.player_pos;
+        self.bursts.update(&self.catalogue.patterns, dt, target, target, &mut self.bullets, &mut self.rng);
+    }
+
+    fn handle_bullet_hits(&mut self) {
+        // Player bullets against nearby enemies. A bullet hurts every enemy
+        // once and is used up when it has no pierce left.
+        for (bullet_id, bullet) in self.bullets.iter_mut() {
+            if bullet.team != Team::Player {
+                continue;
+            }
+            self.enemy_grid.query(&bullet.bounds(), &mut self.candidates);
+            for &id in &self.candidates {
+                let enemy = &mut self.enemies[id];
+                // Enemies killed earlier this tick are waiting for removal, let bullets pass through them
+                if enemy.get_hp() <= 0 || bullet.has_hit(id) || !bullet.overlaps(enemy.collider(), enemy.get_pos()) {
+                    continue;
+                }
+                if bullet.apply_damage_to_enemy(&mut **enemy) <= 0 {
+                    self.despawn.enemies.push(id);
+                }
+                if bullet.register_hit(id) {
+                    self.despawn.bullets.push(bullet_id);
+                    break;
+                }
+            }
+        }
+
+        // Enemy bullets near the player
+        self.bullet_grid.query(&self.player.bounds(), &mut self.candidates);
+        for &id in &self.candidates {
+            let bullet = &self.bullets[id];
+            if bullet.team != Team::Enemy || !bullet.overlaps(&self.player.collider, &self.player.player_pos) {
+                continue;
+            }
+            // Bullets fly through the player while invulnerable
+            if bullet.apply_damage(&mut self.player) {
+                self.despawn.bullets.push(id);
+            }
+        }
+    }
+
+    // The laser hurts the closest enemies along it, as many as it pierces,
+    // and is cut short at the last of them
+    fn handle_beam(&mut self, dt: f32) {
+        let beam = match &mut self.player.beam {
+            Some(beam) => beam,
+            None => {
+                self.beam_damage.clear();
+                return;
+            }
+        };
+        self.enemy_grid.query(&beam.bounds(), &mut self.candidates);
+        self.beam_hits.clear();
+        for &id in &self.candidates {
+            let enemy = &self.enemies[id];
+            if enemy.get_hp() <= 0 {
+                continue;
+            }
+            if let Some(distance) = enemy.collider().ray_hit(enemy.get_pos(), &beam.start, &beam.direction, beam.length) {
+                self.beam_hits.push((distance, id));
+            }
+        }
+        self.beam_hits.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        self.beam_hits.truncate(beam.pierce as usize + 1);
+
+        if self.beam_hits.len() > beam.pierce as usize {
+            beam.length = self.beam_hits[beam.pierce as usize].0;
+        }
+
+        // Hit points are whole, so each enemy's share of a tick adds up until it is worth one
+        let hits = &self.beam_hits;
+        self.beam_damage.retain(|(id, _)| hits.iter().any(|(_, hit)| hit == id));
+        for &(_, id) in &self.beam_hits {
+            let i = match self.beam_damage.iter().position(|(other, _)| *other == id) {
+                Some(i) => i,
+                None => {
+                    self.beam_damage.push((id, 0.0));
+                    self.beam_damage.len() - 1
+                }
+            };
+            let dealt = &mut self.beam_damage[i].1;
+            *dealt += beam.damage * dt;
+            let whole = dealt.floor();
+            if whole >= 1.0 {
+                *dealt -= whole;
+                if self.enemies[id].take_damage(whole as i32) <= 0 {
+                    self.despawn.enemies.push(id);
+                }
+            }
+        }
+    }
+
+    fn handle_bombs(&mut self, input: &InputState, dt: f32) {
+        if input.bomb && !self.bomb_held && self.player.bombs > 0 {
+            self.player.bombs -= 1;
+            self.shockwaves.push(Shockwave::new(self.player.player_pos));
+        }
+        self.bomb_held = input.bomb;
+
+        for shockwave in &mut self.shockwaves {
+            shockwave.update(dt);
+
+            // Cancel the enemy bullets inside the ring
+            self.bullet_grid.query(&shockwave.bounds(), &mut self.candidates);
+            for &id in &self.candidates {
+                let bullet = &self.bullets[id];
+                if bullet.team == Team::Enemy && shockwave.contains(&bullet.center()) {
+                    self.despawn.bullets.push(id);
+                }
+            }
+
+            // Hurt every enemy it reaches once
+            self.enemy_grid.query(&shockwave.bounds(), &mut self.candidates);
+            for &id in &self.candidates {
+                let enemy = &mut self.enemies[id];
+                if enemy.get_hp() <= 0 || !shockwave.contains(&enemy.collider().center(enemy.get_pos())) {
+                    continue;
+                }
+                if let Some(damage) = shockwave.hit(id, enemy.is_boss()) {
+                    if enemy.take_damage(damage) <= 0 {
+                        self.despawn.enemies.push(id);
+                    }
+                }
+            }
+        }
+        self.shockwaves.retain(|shockwave| !shockwave.is_done());
+
+        // Dropped bombs, picked up only while there is room for another charge
+        let player = &mut self.player;
+        self.pickups.retain_mut(|pickup| {
+            pickup.time_left -= dt;
+            if player.bombs < MAX_BOMBS && pickup.collider().overlaps(&pickup.pos, &player.collider, &player.player_pos) {
+                player.bombs += 1;
+                return false;
+            }
+            pickup.time_left > 0.0
+        });
+    }
+
+    // Points, coins and maybe a bomb drop for a killed enemy
+    fn reward_kill(&mut self, enemy: &dyn Enemy) {
+        if enemy.is_boss() { // Controls boss state (1 boss at a time)
+            self.is_boss = false;
+        }
+        self.killed_enemies += 1;
+        self.player.points += enemy.get_points();
+        self.player.coins += enemy.get_coins();
+
+        if self.rng.gen::<f32>() < self.catalogue.enemies[enemy.archetype()].bomb_drop_chance {
+            self.pickups.push(BombPickup::new(enemy.collider().center(enemy.get_pos())));
+        }
+    }
+
+    // Removes everything queued this tick and the bullets that left the screen
+    fn apply_despawns(&mut self) {
+        let mut enemies = std::mem::take(&mut self.despawn.enemies);
+        for id in enemies.drain(..) {
+            // Queued Ids can repeat, only the first removal gives a reward
+            if let Some(enemy) = self.enemies.remove(id) {
+                self.reward_kill(&*enemy);
+            }
+        }
+        self.despawn.enemies = enemies;
+        for id in self.despawn.bullets.drain(..) {
+            self.bullets.remove(id);
+        }
+
+        self.bullets.retain(|_, bullet| !bullet.is_off_screen());
+    }
+
+    // Advances the simulation by one step of dt seconds using the given input
+    // A zero dt step only applies purchases, it is recorded when buying from the shop screen
+    pub fn update(&mut self, dt: f32, input: &InputState) {
+        self.handle_shop_buy(input);
+        if dt == 0.0 {
+            return;
+        }
+        self.shop.close();
+
+        self.time += dt;
+        self.frame += 1;
+
+        self.player.update(input, dt, &mut self.bullets);
+
+        self.update_enemies(dt);
+
+        let homing_target = self.homing_target(input);
+        self.move_bullets(dt, homing_target);
+
+        self.handle_bullet_hits();
+
+        self.handle_beam(dt);
+
+        self.handle_bombs(input, dt);
+
+        self.apply_despawns();
+
+        if self.waves.is_some() {
+            self.update_wave_spawning(dt);
+        } else {
+            self.update_endless_spawning();
+        }
+    }
+
+    pub fn bullet_count(&self, team: Team) -> usize {
+        self.bullets.values().filter(|bullet| bullet.team == team).count()
+    }
+
+    // The living enemy closest to where the player aims, for homing bullets
+    fn homing_target(&self, input: &InputState) -> Option<na::Point2<f32>> {
+        let aim = input.aim.target(&self.player.player_pos);
+        self.enemies.values()
+            .filter(|enemy| enemy.get_hp() > 0)
+            .map(|enemy| enemy.collider().center(enemy.get_pos()))
+            .min_by(|a, b| na::distance(a, &aim).total_cmp(&na::distance(b, &aim)))
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.player.is_dead()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bullet::Bullet;
+    use crate::input::{Aim, WeaponSwitch};
+    use crate::weapon::WeaponLibrary;
+
+    // A standing box enemy worth 10 points and 5 coins, a big wall that hurts
+    // on touch and a pistol and laser, so nothing depends on resources/
+    fn test_world() -> World {
+        let mut catalogue: EnemyCatalogue = toml::from_str(r#"
+            [[enemies]]
+            name = "dummy"
+            behaviour = "chaser"
+            sprite = "/dummy.png"
+            sprite_scale = 1.0
+            collider = "box"
+            hp = { base = 1 }
+            speed = { base = 0 }
+            damage = { base = 1 }
+            coins = { base = 5 }
+            points = { base = 10 }
+            bomb_drop_chance = 0.0
+
+            [[enemies]]
+            name = "wall"
+            behaviour = "chaser"
+            sprite = "/wall.png"
+            sprite_scale = 1.0
+            collider = "box"
+            hp = { base = 100 }
+            speed = { base = 0 }
+            damage = { base = 10 }
+            coins = { base = 0 }
+            points = { base = 0 }
+            bomb_drop_chance = 0.0
+            contact_cooldown = 1.5
+            knockback = 400.0
+        "#).unwrap();
+        catalogue.enemies[0].size = [20.0, 20.0];
+        catalogue.enemies[1].size = [200.0, 200.0];
+        let weapons: WeaponLibrary = toml::from_str(r#"
+            [[weapons]]
+            id = "pistol"
+            name = "PISTOL"
+            kind = "gun"
+
+            [[weapons]]
+            id = "laser"
+            name = "LASER"
+            kind = "laser"
+            range = 500
+        "#).unwrap();
+        let player = Player::new([40.0, 40.0], &weapons).unwrap();
+        let shop = Shop::new(UpgradeTree { upgrades: Vec::new() });
+        let mut world = World::new(1, player, shop, catalogue, None);
+        world.spawn_rate = 0.0;
+        world
+    }
+
+    // A still player bullet right on top of an enemy at `pos`
+    fn spawn_shot_enemy(world: &mut World, pos: na::Point2<f32>) {
+        world.spawn_archetype(0, pos);
+        world.bullets.insert(Bullet::new(Team::Player, pos, pos + na::Vector2::x(), 0.0, 1, 10.0));
+    }
+
+    #[test]
+    fn bullet_kills_enemy_and_pays_out() {
+        let mut world = test_world();
+        let coins = world.player.coins;
+        spawn_shot_enemy(&mut world, na::Point2::new(100.0, 100.0));
+
+        world.update(FIXED_DT, &InputState::default());
+
+        assert_eq!(world.enemies.len(), 0);
+        assert_eq!(world.bullet_count(Team::Player), 0);
+        assert_eq!(world.player.points, 10);
+        assert_eq!(world.player.coins, coins + 5);
+        assert_eq!(world.killed_enemies, 1);
+    }
+
+    #[test]
+    fn enemy_bullet_hurts_player() {
+        let mut world = test_world();
+        let pos = world.player.player_pos;
+        world.bullets.insert(Bullet::new(Team::Enemy, pos, pos + na::Vector2::x(), 0.0, 7, 10.0));
+
+        world.update(FIXED_DT, &InputState::default());
+
+        assert_eq!(world.player.hp, world.player.max_hp() - 7);
+        assert_eq!(world.bullet_count(Team::Enemy), 0);
+    }
+
+    #[test]
+    fn thirty_kills_raise_the_level_in_endless_mode() {
+        let mut world = test_world();
+        for i in 0..30 {
+            spawn_shot_enemy(&mut world, na::Point2::new(50.0 + 40.0 * (i % 10) as f32, 50.0 + 40.0 * (i / 10) as f32));
+        }
+
+        world.update(FIXED_DT, &InputState::default());
+
+        assert_eq!(world.enemies.len(), 0);
+        assert_eq!(world.level, 2);
+        assert_eq!(world.killed_enemies, 0);
+        assert_eq!(world.player.points, 300);
+    }
+
+    // The wall archetype with its left edge `x` to the right of the player, level with it
+    fn spawn_wall(world: &mut World, x: f32) {
+        let pos = world.player.player_pos + na::Vector2::new(x, -100.0);
+        world.spawn_archetype(1, pos);
+    }
+
+    #[test]
+    fn touching_an_enemy_pushes_away_from_its_middle() {
+        let mut world = test_world();
+        spawn_wall(&mut world, 5.0);
+
+        world.update(FIXED_DT, &InputState::default());
+
+        assert_eq!(world.player.hp, world.player.max_hp() - 10);
+        let knockback = world.player.knockback;
+        assert!(knockback.x < 0.0 && knockback.y.abs() < 1e-3, "pushed along {:?}", knockback);
+    }
+
+    #[test]
+    fn each_enemy_hurts_once_per_contact_cooldown() {
+        let mut world = test_world();
+        let pos = world.player.player_pos;
+        spawn_wall(&mut world, 5.0);
+        spawn_wall(&mut world, -205.0);
+        let hits_after = |world: &mut World, ticks: usize| {
+            for _ in 0..ticks {
+                world.update(FIXED_DT, &InputState::default());
+                world.player.player_pos = pos;
+                world.player.knockback = na::Vector2::zeros();
+            }
+            (world.player.max_hp() - world.player.hp) / 10
+        };
+
+        // The first wall hits, the second is held off by the invulnerability
+        // and hits when it ends, 0.8 s in. The first hits again at the end of
+        // its 1.5 s cooldown, once the second hit's invulnerability is over.
+        assert_eq!(hits_after(&mut world, 40), 1);
+        assert_eq!(hits_after(&mut world, 40), 2);
+        assert_eq!(hits_after(&mut world, 30), 3);
+    }
+
+    #[test]
+    fn laser_wears_enemies_down_every_tick() {
+        let mut world = test_world();
+        let pos = world.player.player_pos + na::Vector2::new(100.0, 0.0);
+        world.spawn_archetype(0, pos);
+        let mut input = InputState {
+            fire: true,
+            weapon: Some(WeaponSwitch::Slot(1)),
+            aim: Aim::At(pos + na::Vector2::new(10.0, 10.0)),
+            ..InputState::default()
+        };
+        // A shot's 1 damage over the player's 0.6 seconds between shots
+        let ticks = (0.6 / FIXED_DT).ceil() as usize;
+
+        world.update(FIXED_DT, &input);
+        input.weapon = None;
+        assert_eq!(world.enemies.len(), 1);
+        for _ in 1..ticks - 1 {
+            world.update(FIXED_DT, &input);
+        }
+        assert_eq!(world.enemies.len(), 1);
+        for _ in 0..2 {
+            world.update(FIXED_DT, &input);
+        }
+        assert_eq!(world.enemies.len(), 0);
+        assert_eq!(world.player.points, 10);
+    }
+
+    #[test]
+    fn zero_length_steps_stand_still() {
+        let mut world = test_world();
+        spawn_shot_enemy(&mut world, na::Point2::new(100.0, 100.0));
+
+        world.update(0.0, &InputState::default());
+
+        assert_eq!(world.frame, 0);
+        assert_eq!(world.enemies.len(), 1);
+    }
+}